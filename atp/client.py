@@ -25,13 +25,13 @@ from __future__ import annotations
 
 import json
 import traceback
-from typing import Any, Dict, Optional, Union
+from typing import Any, Dict, List, Optional, Union
 
 import httpx
 
 from atp.config import ATP_SETTLEMENT_URL, ATP_SETTLEMENT_TIMEOUT
 from atp.encryption import ResponseEncryptor
-from atp.schemas import PaymentToken
+from atp.schemas import FeeMode, PaymentToken
 from atp.settlement_client import (
     SettlementServiceClient,
 )
@@ -110,6 +110,8 @@ class ATPClient:
         settlement_timeout: Optional[float] = None,
         wallet_private_key_header: str = "x-wallet-private-key",
         verbose: bool = False,
+        settlement_api_token: Optional[str] = None,
+        max_cost_per_million_usd: Optional[float] = None,
     ):
         """
         Initialize the ATP client.
@@ -126,6 +128,11 @@ class ATPClient:
                 Default: "x-wallet-private-key".
             verbose: If True, enables detailed logging with tracebacks for debugging.
                 Default: False.
+            settlement_api_token: Bearer token for settlement services that require
+                authentication. Default: from ATP_SETTLEMENT_API_TOKEN env var.
+            max_cost_per_million_usd: Largest per-million-token rate in USD accepted
+                by `calculate_payment` and `settle`. Default: from
+                ATP_MAX_COST_PER_MILLION_USD env var or 10000.0.
         """
         self.wallet_private_key = wallet_private_key
         self.wallet_private_key_header = wallet_private_key_header
@@ -141,6 +148,8 @@ class ATPClient:
         self.settlement_client = SettlementServiceClient(
             base_url=self.settlement_service_url,
             timeout=self.settlement_timeout,
+            api_token=settlement_api_token,
+            max_cost_per_million_usd=max_cost_per_million_usd,
         )
         
         # Initialize encryptor for handling encrypted responses
@@ -222,9 +231,12 @@ class ATPClient:
     async def calculate_payment(
         self,
         usage: Dict[str, Any],
-        input_cost_per_million_usd: float,
-        output_cost_per_million_usd: float,
-        payment_token: Union[PaymentToken, str] = PaymentToken.SOL,
+        input_cost_per_million_usd: Optional[float] = None,
+        output_cost_per_million_usd: Optional[float] = None,
+        payment_token: Optional[Union[PaymentToken, str]] = None,
+        blended_cost_per_million_usd: Optional[float] = None,
+        fee_mode: Optional[Union[FeeMode, str]] = None,
+        fee_percent_override: Optional[float] = None,
     ) -> Dict[str, Any]:
         """
         Calculate payment amounts from usage data.
@@ -236,19 +248,39 @@ class ATPClient:
             usage: Usage data containing token counts. Supports same formats as
                 `parse_usage` method.
             input_cost_per_million_usd: Cost per million input tokens in USD.
+                Required together with `output_cost_per_million_usd` unless
+                `blended_cost_per_million_usd` is set.
             output_cost_per_million_usd: Cost per million output tokens in USD.
             payment_token: Token to use for payment. Must be "SOL" or "USDC".
-                Default: PaymentToken.SOL.
+                Default: None (the facilitator's DEFAULT_PAYMENT_TOKEN applies).
+            blended_cost_per_million_usd: Single cost per million total tokens in USD,
+                used instead of the input/output rates.
+            fee_mode: :class:`atp.schemas.FeeMode` or its string value. Default: None
+                (service default, "inclusive").
+            fee_percent_override: Optional processing fee fraction (0.0-0.5) for this
+                request only, if the facilitator supports overrides. Default: None.
                 
         Returns:
             Dict with payment calculation details:
                 - `status` (str): "calculated" or "skipped" (if zero cost)
-                - `pricing` (dict): Pricing information with token counts and costs
+                - `reason` (str, optional): Why the calculation was skipped, one of
+                  the :class:`atp.schemas.SettlementSkipReason` values
+                - `parse_succeeded` (bool): False if the usage format was not
+                  recognized and the token counts fell back to zero
+                - `source_format` (str, optional): Usage format that was detected
+                - `pricing` (dict): Pricing information with token counts and costs,
+                  including the `decimals` used for token units
                 - `payment_amounts` (dict, optional): Payment amounts in token units
                 - `token_price_usd` (float, optional): Current token price in USD
+                - `price_is_fallback` (bool, optional): True if a configured fallback
+                  price was used because the live price was unavailable
+                - `token_info` (dict, optional): `symbol`, `decimals`, and `mint_address`
+                  of the payment token
                 
         Raises:
             SettlementServiceError: If the facilitator returns an error.
+            ValueError: If not exactly one pricing mode is specified, a rate is
+                negative or not finite, or fee_percent_override is out of range.
             
         Example:
             ```python
@@ -263,13 +295,19 @@ class ATPClient:
         payment_token_str = (
             payment_token.value if isinstance(payment_token, PaymentToken) else payment_token
         )
+        fee_mode_str = (
+            fee_mode.value if isinstance(fee_mode, FeeMode) else fee_mode
+        )
         
         if self.verbose:
             logger.debug(
                 f"Calculating payment: usage={usage}, "
                 f"input_cost_per_million_usd={input_cost_per_million_usd}, "
                 f"output_cost_per_million_usd={output_cost_per_million_usd}, "
-                f"payment_token={payment_token_str}"
+                f"blended_cost_per_million_usd={blended_cost_per_million_usd}, "
+                f"payment_token={payment_token_str}, "
+                f"fee_mode={fee_mode_str}, "
+                f"fee_percent_override={fee_percent_override}"
             )
         
         try:
@@ -278,6 +316,9 @@ class ATPClient:
                 input_cost_per_million_usd=input_cost_per_million_usd,
                 output_cost_per_million_usd=output_cost_per_million_usd,
                 payment_token=payment_token_str,
+                blended_cost_per_million_usd=blended_cost_per_million_usd,
+                fee_mode=fee_mode_str,
+                fee_percent_override=fee_percent_override,
             )
             if self.verbose:
                 logger.info(f"Payment calculation successful: {result}")
@@ -292,13 +333,33 @@ class ATPClient:
     async def settle(
         self,
         usage: Dict[str, Any],
-        input_cost_per_million_usd: float,
-        output_cost_per_million_usd: float,
-        recipient_pubkey: str,
-        payment_token: Union[PaymentToken, str] = PaymentToken.SOL,
+        input_cost_per_million_usd: Optional[float] = None,
+        output_cost_per_million_usd: Optional[float] = None,
+        recipient_pubkey: Optional[str] = None,
+        payment_token: Optional[Union[PaymentToken, str]] = None,
         skip_preflight: bool = False,
         commitment: str = "confirmed",
         wallet_private_key: Optional[str] = None,
+        blended_cost_per_million_usd: Optional[float] = None,
+        metadata: Optional[Dict[str, str]] = None,
+        fee_recipient: Optional[str] = None,
+        max_total_usd: Optional[float] = None,
+        max_total_token: Optional[float] = None,
+        blockhash_commitment: Optional[str] = None,
+        referral_pubkey: Optional[str] = None,
+        referral_share_bps: Optional[int] = None,
+        strict: bool = False,
+        fee_mode: Optional[Union[FeeMode, str]] = None,
+        estimate_only: bool = False,
+        rpc_url_override: Optional[str] = None,
+        max_price_age_secs: Optional[float] = None,
+        idempotency_key: Optional[str] = None,
+        recipients: Optional[List[Dict[str, Any]]] = None,
+        skip_balance_check: bool = False,
+        signer_id: Optional[str] = None,
+        callback_url: Optional[str] = None,
+        request_id: Optional[str] = None,
+        fee_percent_override: Optional[float] = None,
     ) -> Dict[str, Any]:
         """
         Execute a settlement payment on Solana blockchain.
@@ -311,11 +372,15 @@ class ATPClient:
             usage: Usage data containing token counts. Supports same formats as
                 `parse_usage` method.
             input_cost_per_million_usd: Cost per million input tokens in USD.
+                Required unless `blended_cost_per_million_usd` is set. Default: None.
             output_cost_per_million_usd: Cost per million output tokens in USD.
+                Required unless `blended_cost_per_million_usd` is set. Default: None.
             recipient_pubkey: Solana public key of the recipient wallet (base58 encoded).
-                This wallet receives the net payment after fees.
-            payment_token: Token to use for payment. Currently only "SOL" is supported
-                for automatic settlement. Default: PaymentToken.SOL.
+                This wallet receives the net payment after fees. Required unless
+                `recipients` is given. Default: None.
+            payment_token: Token to use for payment. Must be "SOL" or "USDC". USDC is
+                settled as SPL token transfers between associated token accounts.
+                Default: None (the facilitator's DEFAULT_PAYMENT_TOKEN applies).
             skip_preflight: Whether to skip preflight simulation. Default: False.
             commitment: Solana commitment level for transaction confirmation:
                 - "processed": Fastest, but may be rolled back
@@ -324,17 +389,96 @@ class ATPClient:
                 Default: "confirmed".
             wallet_private_key: Wallet private key to use for payment. If not provided,
                 uses the client's default wallet_private_key.
+            blended_cost_per_million_usd: Single cost per million total tokens in USD,
+                used instead of the input/output rates. Default: None.
+            metadata: Optional string-to-string map (e.g. invoice or order IDs) stored
+                with the settlement record and echoed back in the response. Never
+                written on-chain. Bounded by `MAX_METADATA_ENTRIES` and
+                `MAX_METADATA_BYTES` in :mod:`atp.settlement_client`. Default: None.
+            fee_recipient: Optional Solana public key to receive the processing fee
+                instead of the treasury. Only honored by facilitators that support fee
+                routing; check `payment` in the result. Default: None.
+            max_total_usd: Optional ceiling on the cost in USD. The settlement is
+                quoted with `calculate_payment` first and not sent if the quote
+                exceeds it. Default: None.
+            max_total_token: Optional ceiling on the total amount in `payment_token`
+                units (e.g. SOL), checked against the same quote. The settled amount
+                can differ from the quote by price movement between the two calls.
+                Default: None.
+            blockhash_commitment: Optional commitment level used only for the recent
+                blockhash fetch (e.g. "finalized" for stability under congestion).
+                Default: None.
+            referral_pubkey: Optional Solana public key of a referral partner to
+                receive a share of the processing fee, if the facilitator supports
+                referral splits (otherwise ignored). Default: None.
+            referral_share_bps: Share of the processing fee for `referral_pubkey`,
+                in basis points (0-10000). Default: None.
+            strict: If True, asks the facilitator to reject a skipped settlement
+                (zero or below-minimum cost) with HTTP 402 instead of returning status
+                "skipped". Facilitators without strict mode ignore it. Default: False.
+            fee_mode: :class:`atp.schemas.FeeMode` or its string value. "inclusive"
+                deducts the fee from the total; "exclusive" adds it on top so the
+                recipient receives the full amount. Default: None (service default).
+            estimate_only: If True, returns the computed payment details with status
+                "estimated" without touching the chain. No wallet key is required.
+                Default: False.
+            rpc_url_override: Optional Solana RPC URL for this settlement only. The
+                facilitator only honors it for admin-authenticated callers.
+                Default: None.
+            max_price_age_secs: Optional maximum age in seconds of the token price used
+                for this settlement. Default: None.
+            idempotency_key: Optional key forwarded as the `Idempotency-Key` header.
+                Retries are only deduplicated if the facilitator supports it; the
+                current one does not. Default: None.
+            recipients: Optional list of `{"pubkey": ..., "weight": ...}` dicts to split
+                the net payment across several wallets in one transaction. Leave
+                `recipient_pubkey` unset when using it. Default: None.
+            skip_balance_check: If True, skips the facilitator's payer balance pre-check
+                (which returns an `insufficient_funds` error with the shortfall).
+                Default: False.
+            signer_id: Optional identifier of a keypair held by the facilitator. When
+                set, the facilitator signs with that keypair and the client's default
+                wallet_private_key is not sent. Passing `wallet_private_key` as well
+                raises ValueError. Default: None.
+            callback_url: Optional URL the facilitator notifies with the settlement
+                result once it completes. Callbacks are HMAC-signed with the
+                facilitator's webhook secret. Default: None.
+            request_id: Optional correlation id sent as the `X-Request-Id` header so
+                the facilitator's logs for this settlement can be matched to yours.
+                Default: None.
+            fee_percent_override: Optional processing fee fraction (0.0-0.5) for this
+                settlement only, e.g. for partner-tier pricing. Ignored by facilitators
+                without override support. Default: None.
                 
         Returns:
             Dict with payment details:
-                - `status` (str): "paid" if successful, "skipped" if zero cost
+                - `status` (str): "paid" if successful, "skipped" if zero cost,
+                  "submitted_unconfirmed" if the transaction was sent but confirmation
+                  timed out, "estimated" for `estimate_only` requests
+                  (see :class:`atp.schemas.SettlementStatus`)
+                - `reason` (str, optional): Why the settlement was skipped, one of
+                  the :class:`atp.schemas.SettlementSkipReason` values
+                - `settlement_id` (str): Facilitator-assigned identifier for this
+                  settlement, present even when nothing was sent on-chain
                 - `transaction_signature` (str, optional): Solana transaction signature
-                - `pricing` (dict): Complete cost breakdown
-                - `payment` (dict, optional): Payment details including amounts and splits
+                - `pricing` (dict): Complete cost breakdown, including the `decimals`
+                  used for token units
+                - `price_is_fallback` (bool, optional): True if a configured fallback
+                  price was used because the live price was unavailable
+                - `payment` (dict, optional): Payment details including amounts and
+                  splits, `effective_fee_bps` (fee actually charged), and
+                  `recent_blockhash` (blockhash the transaction was built with)
                 
         Raises:
-            SettlementServiceError: If the facilitator returns an error.
-            ValueError: If wallet_private_key is not provided.
+            SettlementServiceError: If the facilitator returns an error, or with
+                error_code "max_total_exceeded" if a spend ceiling is exceeded.
+            ValueError: If wallet_private_key is not provided (and neither
+                estimate_only nor signer_id is set), if both wallet_private_key and
+                signer_id are passed, if not exactly one pricing mode is specified,
+                if a rate is negative or not finite, if the referral fields are
+                incomplete or out of range, if not exactly one of recipient_pubkey
+                and recipients is given, if fee_percent_override is out of range, or
+                if metadata is not a string-to-string map within the size limits.
             
         Example:
             ```python
@@ -347,11 +491,16 @@ class ATPClient:
             )
             ```
         """
+        if wallet_private_key is not None and signer_id is not None:
+            error_msg = "Specify either wallet_private_key or signer_id, not both"
+            logger.error(error_msg)
+            raise ValueError(error_msg)
+
         private_key = wallet_private_key or self.wallet_private_key
-        if not private_key:
+        if not private_key and not estimate_only and signer_id is None:
             error_msg = (
                 "wallet_private_key must be provided either in client initialization "
-                "or as a parameter to this method"
+                "or as a parameter to this method, unless signer_id is set"
             )
             if self.verbose:
                 logger.error(f"{error_msg}\n{traceback.format_exc()}")
@@ -362,21 +511,49 @@ class ATPClient:
         payment_token_str = (
             payment_token.value if isinstance(payment_token, PaymentToken) else payment_token
         )
+        fee_mode_str = (
+            fee_mode.value if isinstance(fee_mode, FeeMode) else fee_mode
+        )
         
         if self.verbose:
             logger.debug(
                 f"Settling payment: usage={usage}, "
                 f"input_cost_per_million_usd={input_cost_per_million_usd}, "
                 f"output_cost_per_million_usd={output_cost_per_million_usd}, "
+                f"blended_cost_per_million_usd={blended_cost_per_million_usd}, "
                 f"recipient_pubkey={recipient_pubkey}, "
                 f"payment_token={payment_token_str}, "
                 f"skip_preflight={skip_preflight}, "
-                f"commitment={commitment}"
+                f"commitment={commitment}, "
+                f"metadata={metadata}, "
+                f"fee_recipient={fee_recipient}, "
+                f"max_total_usd={max_total_usd}, "
+                f"max_total_token={max_total_token}, "
+                f"blockhash_commitment={blockhash_commitment}, "
+                f"referral_pubkey={referral_pubkey}, "
+                f"referral_share_bps={referral_share_bps}, "
+                f"strict={strict}, "
+                f"fee_mode={fee_mode_str}, "
+                f"estimate_only={estimate_only}, "
+                f"rpc_url_override={rpc_url_override}, "
+                f"max_price_age_secs={max_price_age_secs}, "
+                f"idempotency_key={idempotency_key}, "
+                f"recipients={recipients}, "
+                f"skip_balance_check={skip_balance_check}, "
+                f"signer_id={signer_id}, "
+                f"callback_url={callback_url}, "
+                f"request_id={request_id}, "
+                f"fee_percent_override={fee_percent_override}"
             )
         
         try:
             result = await self.settlement_client.settle(
-                private_key=private_key,
+                # No key is sent for estimates (they never touch the chain) or when
+                # the facilitator signs with a pre-loaded signer_id; only the client's
+                # default key can reach this point alongside signer_id
+                private_key=(
+                    None if estimate_only or signer_id is not None else private_key
+                ),
                 usage=usage,
                 input_cost_per_million_usd=input_cost_per_million_usd,
                 output_cost_per_million_usd=output_cost_per_million_usd,
@@ -384,6 +561,26 @@ class ATPClient:
                 payment_token=payment_token_str,
                 skip_preflight=skip_preflight,
                 commitment=commitment,
+                blended_cost_per_million_usd=blended_cost_per_million_usd,
+                metadata=metadata,
+                fee_recipient=fee_recipient,
+                max_total_usd=max_total_usd,
+                max_total_token=max_total_token,
+                blockhash_commitment=blockhash_commitment,
+                referral_pubkey=referral_pubkey,
+                referral_share_bps=referral_share_bps,
+                strict=strict,
+                fee_mode=fee_mode_str,
+                estimate_only=estimate_only,
+                rpc_url_override=rpc_url_override,
+                max_price_age_secs=max_price_age_secs,
+                idempotency_key=idempotency_key,
+                recipients=recipients,
+                skip_balance_check=skip_balance_check,
+                signer_id=signer_id,
+                callback_url=callback_url,
+                request_id=request_id,
+                fee_percent_override=fee_percent_override,
             )
             if self.verbose:
                 logger.info(f"Settlement successful: {result}")
@@ -101,3 +101,17 @@ ATP_SETTLEMENT_URL = os.getenv(
 # Settlement operations can take longer due to blockchain confirmation times
 # Default: 300 seconds (5 minutes) - can be overridden via environment variable or middleware parameter
 ATP_SETTLEMENT_TIMEOUT = _float_env("ATP_SETTLEMENT_TIMEOUT") or 300.0
+
+# Settlement Service API token
+# Sent as "Authorization: Bearer <token>" when the settlement service has API_TOKENS configured.
+# Leave unset for services that do not require authentication.
+ATP_SETTLEMENT_API_TOKEN = os.getenv("ATP_SETTLEMENT_API_TOKEN")
+
+# Upper bound on any per-million-token rate the SDK will send (in USD)
+# Guards against unit mistakes (e.g. passing a per-token price in micro-USD) draining a payer wallet.
+# Default: 10000 USD per million tokens - raise via environment variable for unusually expensive models.
+# 0 is honored and rejects any non-zero rate. Read once at import; pass max_cost_per_million_usd to
+# SettlementServiceClient / ATPClient to override it per client.
+ATP_MAX_COST_PER_MILLION_USD = _float_env("ATP_MAX_COST_PER_MILLION_USD")
+if ATP_MAX_COST_PER_MILLION_USD is None:
+    ATP_MAX_COST_PER_MILLION_USD = 10000.0
@@ -33,21 +33,29 @@ in front if needed.
 from __future__ import annotations
 
 import json
+import math
+import re
+import uuid
 from typing import Any, Callable, Dict, List, Optional, Set
 
 from fastapi import HTTPException, Request, Response
+from fastapi.responses import JSONResponse
 from loguru import logger
 from starlette.middleware.base import BaseHTTPMiddleware
 from starlette.types import ASGIApp
 
 from atp import config
 from atp.encryption import ResponseEncryptor
-from atp.schemas import PaymentToken
+from atp.schemas import PaymentToken, SettlementStatus
 from atp.settlement_client import (
     SettlementServiceClient,
     SettlementServiceError,
 )
 
+# Incoming X-Request-Id values are reused only if they are short and use a safe
+# charset; anything else is replaced so it cannot be used to inject log lines.
+_REQUEST_ID_PATTERN = re.compile(r"^[A-Za-z0-9._:-]{1,128}$")
+
 
 class ATPSettlementMiddleware(BaseHTTPMiddleware):
     """
@@ -114,8 +122,8 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
     The middleware provides two error handling modes:
     - **fail_on_settlement_error=False** (default): Returns encrypted response with
       settlement error details. Useful for debugging and graceful degradation.
-    - **fail_on_settlement_error=True**: Raises HTTPException when settlement fails.
-      Useful for strict payment requirements.
+    - **fail_on_settlement_error=True**: Returns an error response with the settlement
+      service's status code when settlement fails. Useful for strict payment requirements.
 
     **Response Modifications:**
 
@@ -123,8 +131,17 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
     - `atp_usage`: Normalized usage data (input_tokens, output_tokens, total_tokens)
     - `atp_settlement`: Settlement details including transaction signature and payment breakdown
     - `atp_settlement_status`: Status of settlement ("paid", "failed", etc.)
+      A settlement whose transaction was sent but not confirmed in time is reported
+      in `atp_settlement` with status "submitted_unconfirmed" and its signature.
     - `atp_message`: Informational message about response encryption status
 
+    Each processed request is tagged with a correlation id: the incoming `X-Request-Id`
+    header if it is a short, safe token (up to 128 characters of letters, digits, and
+    `.`, `_`, `:`, `-`), otherwise a generated one. It is forwarded to the settlement
+    service's parse-usage and settle calls, prefixed to every log line the middleware
+    emits for the request, and echoed in the `X-Request-Id` response header, including
+    on error responses.
+
     **Attributes:**
 
         allowed_endpoints (Set[str]): Set of endpoint paths to apply settlement to.
@@ -134,7 +151,7 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
         payment_token (PaymentToken): Token to use for payment (SOL or USDC).
         skip_preflight (bool): Whether to skip preflight simulation for Solana transactions.
         commitment (str): Solana commitment level (processed|confirmed|finalized).
-        fail_on_settlement_error (bool): Whether to raise exception on settlement failure.
+        fail_on_settlement_error (bool): Whether to fail the request on settlement failure.
         settlement_service_client (SettlementServiceClient): Client for settlement service API.
         encryptor (ResponseEncryptor): Encryptor for protecting agent responses.
 
@@ -178,7 +195,7 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
 
     - The middleware only processes successful responses (status_code < 400).
     - If usage data cannot be parsed (no input_tokens, output_tokens, or total_tokens
-      in the response), the middleware raises HTTP 422 with an error message.
+      in the response), the middleware returns HTTP 422 with an error message.
     - Settlement operations may take time due to blockchain confirmation. Increase
       `settlement_timeout` if you experience timeout errors even when payments succeed.
     - The treasury pubkey is configured on the settlement service and cannot be
@@ -205,6 +222,7 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
         settlement_service_url: Optional[str] = None,
         fail_on_settlement_error: bool = False,
         settlement_timeout: Optional[float] = None,
+        settlement_api_token: Optional[str] = None,
     ):
         """
         Initialize the ATP settlement middleware.
@@ -229,12 +247,15 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
             settlement_service_url: Base URL of the settlement service. If not provided, uses
                 ATP_SETTLEMENT_URL environment variable (default: http://localhost:8001).
                 The middleware always uses the settlement service for all settlement operations.
-            fail_on_settlement_error: If True, raises HTTPException when settlement fails (default: False).
+            fail_on_settlement_error: If True, returns an error response when settlement fails (default: False).
                 If False, returns the response with settlement error info instead of failing the request.
             settlement_timeout: Timeout in seconds for settlement service requests. User-configurable parameter.
                 Default: from ATP_SETTLEMENT_TIMEOUT env var or 300.0 (5 minutes). Settlement operations may
                 take longer due to blockchain confirmation times. Increase this value if you experience timeout
                 errors even when payments are successfully sent.
+            settlement_api_token: Bearer token for settlement services that require
+                authentication. Default: from ATP_SETTLEMENT_API_TOKEN env var (unset
+                means no Authorization header is sent).
         """
         super().__init__(app)
         self.allowed_endpoints: Set[str] = set(allowed_endpoints)
@@ -260,6 +281,7 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
         self.settlement_service_client = SettlementServiceClient(
             base_url=service_url,
             timeout=settlement_timeout,
+            api_token=settlement_api_token,
         )
         # Initialize encryptor for protecting agent responses
         self.encryptor = ResponseEncryptor()
@@ -295,8 +317,52 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
         """
         return request.headers.get(self.wallet_private_key_header)
 
+    def _resolve_request_id(self, request: Request) -> str:
+        """
+        Return the correlation id for a request.
+
+        Reuses the incoming `X-Request-Id` header when it is at most 128 characters
+        of letters, digits, and `.`, `_`, `:`, `-`. Otherwise (missing or unsafe)
+        a new random id is generated.
+
+        Args:
+            request: The incoming HTTP request.
+
+        Returns:
+            The request id to log, forward to the settlement service, and echo back.
+        """
+        incoming = request.headers.get("x-request-id")
+        if incoming and _REQUEST_ID_PATTERN.match(incoming):
+            return incoming
+        return uuid.uuid4().hex
+
+    @staticmethod
+    def _error_response(
+        status_code: int, detail: Any, headers: Dict[str, str]
+    ) -> JSONResponse:
+        """
+        Build an error response in FastAPI's `{"detail": ...}` shape.
+
+        Exceptions raised inside `BaseHTTPMiddleware.dispatch` bypass the app's
+        exception handlers and surface as a bare 500, so the middleware returns
+        its errors instead of raising them.
+
+        Args:
+            status_code: HTTP status code of the response.
+            detail: Error detail for the response body.
+            headers: Response headers (at least `X-Request-Id`).
+
+        Returns:
+            The JSON error response.
+        """
+        return JSONResponse(
+            status_code=status_code,
+            content={"detail": detail},
+            headers=headers,
+        )
+
     async def _parse_usage_from_response(
-        self, response_body: bytes
+        self, response_body: bytes, request_id: Optional[str] = None
     ) -> Optional[Dict[str, Any]]:
         """
         Parse usage information from response body using the settlement service.
@@ -307,6 +373,8 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
 
         Args:
             response_body: Raw response body bytes.
+            request_id: Optional correlation id forwarded to the settlement service
+                and included in log lines.
 
         Returns:
             Parsed usage dict with normalized keys (input_tokens, output_tokens, total_tokens),
@@ -321,7 +389,8 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
             # Send entire response body to settlement service for parsing
             # The service handles all format detection and nested structure traversal
             parsed_usage = await self.settlement_service_client.parse_usage(
-                usage_data=data
+                usage_data=data,
+                request_id=request_id,
             )
 
             # Check if we got valid token counts
@@ -335,18 +404,18 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
             return None
         except (json.JSONDecodeError, UnicodeDecodeError) as e:
             logger.debug(
-                f"Failed to parse response body for usage: {e}"
+                f"[{request_id}] Failed to parse response body for usage: {e}"
             )
             return None
         except SettlementServiceError as e:
             # If settlement service can't parse usage, log and return None
             logger.debug(
-                f"Settlement service could not parse usage from response: {e}"
+                f"[{request_id}] Settlement service could not parse usage from response: {e}"
             )
             return None
         except Exception as e:
             logger.debug(
-                f"Unexpected error parsing usage: {e}"
+                f"[{request_id}] Unexpected error parsing usage: {e}"
             )
             return None
         
@@ -392,9 +461,10 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
             payment is confirmed. If payment fails, response remains encrypted with
             error details.
 
-        **Raises:**
-            HTTPException: If wallet is required but missing (402 Payment Required), or if
-                `fail_on_settlement_error=True` and settlement fails.
+        Errors are returned as `{"detail": ...}` JSON responses carrying the
+        `X-Request-Id` header rather than raised: 402 if the wallet is missing, 422
+        if no usage is found, and the settlement error's status code if
+        `fail_on_settlement_error=True` and settlement fails.
 
         **Response Modifications:**
             - Adds `atp_usage` field with normalized token counts
@@ -402,14 +472,16 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
             - Adds `atp_settlement_status` field with payment status
             - Adds `atp_message` field with encryption status message
             - Removes `Content-Length` and `Content-Encoding` headers (recalculated)
+            - Sets the `X-Request-Id` header to the request's correlation id
 
         **Error Scenarios:**
             - Missing wallet (if required): Returns 402 Payment Required
-            - No usage data: Raises 422 with message that endpoint must output
+            - No usage data: Returns 422 with message that endpoint must output
                 input_tokens, output_tokens, or total_tokens (or equivalent usage fields)
             - Encryption failure: Returns 500 with error (response not exposed)
             - Settlement failure: Returns encrypted response with error details
-                (or raises exception if `fail_on_settlement_error=True`)
+                (or an error response if `fail_on_settlement_error=True`, carrying
+                `Retry-After` when the settlement service was rate limited)
         """
         path = request.url.path
 
@@ -417,13 +489,18 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
         if not self._should_process(path):
             return await call_next(request)
 
+        # Correlation id shared with the settlement service and echoed to the caller
+        request_id = self._resolve_request_id(request)
+        request_id_header = {"X-Request-Id": request_id}
+
         # Extract wallet private key
         private_key = self._extract_wallet_private_key(request)
         
         if not private_key:
-            raise HTTPException(
-                status_code=402,
-                detail="Payment required. Missing wallet private key in header. Please provide a valid wallet private key and ensure payment succeeds. The header should be x-wallet-private-key.",
+            return self._error_response(
+                402,
+                "Payment required. Missing wallet private key in header. Please provide a valid wallet private key and ensure payment succeeds. The header should be x-wallet-private-key.",
+                request_id_header,
             )
 
         # Execute the endpoint
@@ -431,6 +508,7 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
 
         # Only process successful responses
         if response.status_code >= 400:
+            response.headers["x-request-id"] = request_id
             return response
 
         # Parse usage from response using settlement service
@@ -438,21 +516,24 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
         async for chunk in response.body_iterator:
             response_body += chunk
 
-        usage = await self._parse_usage_from_response(response_body)
+        usage = await self._parse_usage_from_response(
+            response_body, request_id=request_id
+        )
 
         if not usage:
             logger.warning(
-                f"No usage data found in response for {path}. "
+                f"[{request_id}] No usage data found in response for {path}. "
                 "Settlement service could not parse usage from response body."
             )
-            raise HTTPException(
-                status_code=422,
-                detail=(
+            return self._error_response(
+                422,
+                (
                     "Endpoint must include token usage in the response. "
                     "Response must contain at least one of: input_tokens, output_tokens, or total_tokens "
                     "(or equivalent fields such as prompt_tokens/completion_tokens in a usage object). "
                     f"No parseable usage data found for {path}."
                 ),
+                request_id_header,
             )
 
         # Encrypt the agent response before payment verification
@@ -467,7 +548,7 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
             original_encrypted_data = encrypted_response_data.copy()
         except Exception as e:
             logger.error(
-                f"Failed to encrypt response: {e}. "
+                f"[{request_id}] Failed to encrypt response: {e}. "
                 "This is a security issue - cannot proceed without encryption.",
                 exc_info=True,
             )
@@ -483,6 +564,7 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
             new_headers.pop("Content-Length", None)
             new_headers.pop("content-encoding", None)
             new_headers.pop("Content-Encoding", None)
+            new_headers["x-request-id"] = request_id
             return Response(
                 content=json.dumps(error_response).encode("utf-8"),
                 status_code=500,
@@ -504,6 +586,7 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
                 payment_token=self.payment_token.value,
                 skip_preflight=self.skip_preflight,
                 commitment=self.commitment,
+                request_id=request_id,
             )
         except SettlementServiceError as e:
             # Handle settlement service errors with detailed information
@@ -514,12 +597,14 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
             is_client_error = e.status_code and 400 <= e.status_code < 500
             
             if self.fail_on_settlement_error:
-                # Raise HTTPException with appropriate status code
-                status_code = e.status_code or 500
-                detail = e.error_detail or str(e)
-                raise HTTPException(
-                    status_code=status_code,
-                    detail=detail,
+                # Fail the request with the settlement service's status code
+                headers = dict(request_id_header)
+                if e.retry_after is not None:
+                    headers["Retry-After"] = str(int(math.ceil(e.retry_after)))
+                return self._error_response(
+                    e.status_code or 500,
+                    e.error_detail or str(e),
+                    headers,
                 )
             
             # Store error info to include in response
@@ -529,29 +614,36 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
             # Log with appropriate level based on error type
             if is_client_error:
                 logger.warning(
-                    f"Settlement failed (client error {e.status_code}): {e.error_detail or str(e)}"
+                    f"[{request_id}] Settlement failed (client error {e.status_code}): {e.error_detail or str(e)}"
                 )
             else:
                 logger.error(
-                    f"Settlement failed (server error {e.status_code or 'unknown'}): {e.error_detail or str(e)}"
+                    f"[{request_id}] Settlement failed (server error {e.status_code or 'unknown'}): {e.error_detail or str(e)}"
                 )
-        except HTTPException:
-            # Re-raise HTTPExceptions (these are intentional errors like 401, 403, etc.)
+        except HTTPException as e:
+            # Pass through intentional errors like 401, 403, etc.
             if self.fail_on_settlement_error:
-                raise
+                return self._error_response(
+                    e.status_code,
+                    e.detail,
+                    {**(e.headers or {}), **request_id_header},
+                )
             settlement_error = {
                 "error": "Settlement failed",
                 "status_code": 500,
                 "detail": "Settlement service returned an error",
             }
-            logger.warning("Settlement failed with HTTPException, but continuing with response")
+            logger.warning(
+                f"[{request_id}] Settlement failed with HTTPException, but continuing with response"
+            )
         except Exception as e:
             # Handle unexpected errors
-            logger.error(f"Unexpected settlement error: {e}", exc_info=True)
+            logger.error(f"[{request_id}] Unexpected settlement error: {e}", exc_info=True)
             if self.fail_on_settlement_error:
-                raise HTTPException(
-                    status_code=500,
-                    detail=f"Settlement failed: {str(e)}",
+                return self._error_response(
+                    500,
+                    f"Settlement failed: {str(e)}",
+                    request_id_header,
                 )
             # Store error info to include in response
             settlement_error = {
@@ -560,7 +652,7 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
                 "type": type(e).__name__,
             }
             logger.warning(
-                f"Settlement failed but continuing with response: {e}"
+                f"[{request_id}] Settlement failed but continuing with response: {e}"
             )
 
         # Process payment result and decrypt response only if payment succeeded
@@ -578,21 +670,38 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
                 has_transaction = bool(
                     payment_result.get("transaction_signature")
                 )
+                settlement_id = payment_result.get("settlement_id") or "N/A"
                 
-                if payment_status == "paid" and has_transaction:
+                if (
+                    payment_status == SettlementStatus.PAID.value
+                    and has_transaction
+                ):
                     payment_succeeded = True
                     # Decrypt the response now that payment is confirmed
                     final_response_data = self.encryptor.decrypt_response_data(
                         final_response_data
                     )
                     logger.info(
-                        f"Payment confirmed (tx: {payment_result.get('transaction_signature', 'N/A')[:16]}...), "
+                        f"[{request_id}] Payment confirmed (settlement: {settlement_id}, "
+                        f"tx: {payment_result.get('transaction_signature', 'N/A')[:16]}...), "
                         "response decrypted"
                     )
+                elif (
+                    payment_status
+                    == SettlementStatus.SUBMITTED_UNCONFIRMED.value
+                ):
+                    # The transaction was sent but not confirmed in time. Funds may
+                    # have moved, so surface the signature instead of a generic failure.
+                    logger.warning(
+                        f"[{request_id}] Payment submitted but unconfirmed "
+                        f"(settlement: {settlement_id}, "
+                        f"tx: {payment_result.get('transaction_signature') or 'N/A'}). "
+                        "Response will remain encrypted."
+                    )
                 else:
                     logger.warning(
-                        f"Payment not confirmed. Status: '{payment_status}', "
-                        f"Has transaction: {has_transaction}. "
+                        f"[{request_id}] Payment not confirmed. Status: '{payment_status}', "
+                        f"Settlement: {settlement_id}, Has transaction: {has_transaction}. "
                         "Response will remain encrypted."
                     )
                 final_response_data["atp_settlement"] = payment_result
@@ -601,12 +710,22 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
                 final_response_data["atp_settlement"] = settlement_error
                 final_response_data["atp_settlement_status"] = "failed"
                 logger.warning(
-                    "Payment failed, response remains encrypted. "
+                    f"[{request_id}] Payment failed, response remains encrypted. "
                     "User cannot see agent output."
                 )
             
             # If payment didn't succeed, add a message indicating the response is encrypted
-            if not payment_succeeded:
+            if (
+                payment_result
+                and payment_status
+                == SettlementStatus.SUBMITTED_UNCONFIRMED.value
+            ):
+                final_response_data["atp_message"] = (
+                    "Agent response is encrypted. Payment was submitted but not yet "
+                    "confirmed. Verify the transaction signature before retrying to "
+                    "avoid paying twice."
+                )
+            elif not payment_succeeded:
                 final_response_data["atp_message"] = (
                     "Agent response is encrypted. Payment required to decrypt. "
                     "Please provide a valid wallet private key and ensure payment succeeds."
@@ -615,7 +734,7 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
             response_body = json.dumps(final_response_data).encode("utf-8")
         except Exception as e:
             logger.error(
-                f"Failed to process payment and decrypt response: {e}",
+                f"[{request_id}] Failed to process payment and decrypt response: {e}",
                 exc_info=True,
             )
             # On error, return encrypted response with error info
@@ -632,7 +751,8 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
                 response_body = json.dumps(error_response).encode("utf-8")
             except Exception as e2:
                 logger.error(
-                    f"Failed to create error response: {e2}", exc_info=True
+                    f"[{request_id}] Failed to create error response: {e2}",
+                    exc_info=True,
                 )
                 # Last resort: return original encrypted response
                 response_body = json.dumps(original_encrypted_data).encode(
@@ -647,6 +767,7 @@ class ATPSettlementMiddleware(BaseHTTPMiddleware):
         new_headers.pop("Content-Length", None)
         new_headers.pop("content-encoding", None)
         new_headers.pop("Content-Encoding", None)
+        new_headers["x-request-id"] = request_id
 
         return Response(
             content=response_body,
@@ -675,7 +796,8 @@ def create_settlement_middleware(
         output_cost_per_million_usd: Cost per million output tokens in USD.
         **kwargs: Passed through to :class:`ATPSettlementMiddleware` (e.g.
             ``recipient_pubkey``, ``wallet_private_key_header``, ``payment_token``,
-            ``settlement_service_url``, ``fail_on_settlement_error``, ``settlement_timeout``).
+            ``settlement_service_url``, ``fail_on_settlement_error``, ``settlement_timeout``,
+            ``settlement_api_token``).
 
     Returns:
         A subclass of :class:`ATPSettlementMiddleware` whose constructor takes
@@ -107,6 +107,39 @@ class PaymentToken(str, Enum):
     USDC = "USDC"
 
 
+class FeeMode(str, Enum):
+    """How the processing fee is applied to a priced amount."""
+
+    # Fee is deducted from the total; the recipient receives the remainder.
+    INCLUSIVE = "inclusive"
+    # Recipient receives the full amount; the fee is added on top for the payer.
+    EXCLUSIVE = "exclusive"
+
+
+class SettlementStatus(str, Enum):
+    """Settlement status values returned by the settlement service."""
+
+    PAID = "paid"
+    SKIPPED = "skipped"
+    # The transaction was sent but confirmation timed out. Funds may have
+    # moved; verify the signature before retrying to avoid paying twice.
+    SUBMITTED_UNCONFIRMED = "submitted_unconfirmed"
+    # Amounts computed for an estimate_only request; nothing was sent.
+    ESTIMATED = "estimated"
+
+
+class SettlementSkipReason(str, Enum):
+    """Reason reported by the settlement service when status is "skipped".
+
+    Values are stable snake_case strings so clients can branch on them
+    instead of matching free-form messages.
+    """
+
+    ZERO_COST = "zero_cost"
+    BELOW_MINIMUM = "below_minimum"
+    ORACLE_DEFERRED = "oracle_deferred"
+
+
 class ATPSettlementMiddlewareConfig(BaseModel):
     """Configuration schema for ATP Settlement Middleware.
     
@@ -178,7 +211,7 @@ class ATPSettlementMiddlewareConfig(BaseModel):
     fail_on_settlement_error: bool = Field(
         default=False,
         description=(
-            "If True, raises HTTPException when settlement fails (default: False). "
+            "If True, returns an error response when settlement fails (default: False). "
             "If False, returns the response with settlement error info instead of failing the request."
         ),
     )
@@ -192,6 +225,14 @@ class ATPSettlementMiddlewareConfig(BaseModel):
         ),
         gt=0.0,
     )
+    settlement_api_token: Optional[str] = Field(
+        default=None,
+        description=(
+            "Bearer token for settlement services that require authentication. "
+            "If not provided, uses ATP_SETTLEMENT_API_TOKEN environment variable. "
+            "When neither is set, no Authorization header is sent."
+        ),
+    )
 
     class Config:
         """Pydantic configuration."""
@@ -9,12 +9,18 @@ logic to the immutable service.
 from __future__ import annotations
 
 import json
-from typing import Any, Dict, Optional
+import math
+from typing import Any, Dict, List, Optional, Union
 
 import httpx
 from loguru import logger
 
-from atp.config import ATP_SETTLEMENT_URL, ATP_SETTLEMENT_TIMEOUT
+from atp.config import (
+    ATP_MAX_COST_PER_MILLION_USD,
+    ATP_SETTLEMENT_API_TOKEN,
+    ATP_SETTLEMENT_TIMEOUT,
+    ATP_SETTLEMENT_URL,
+)
 
 
 class SettlementServiceError(Exception):
@@ -32,12 +38,24 @@ class SettlementServiceError(Exception):
         error_type (Optional[str]): Type/category of the error (e.g., "Client error",
             "Server error", "Timeout", "Connection error").
         response_body (Optional[Dict[str, Any]]): Full response body if available.
+        error_code (Optional[str]): Machine-readable error code from a structured
+            error response (e.g. "invalid_keypair", "price_unavailable",
+            "rpc_failure", "unsupported_token"), if the service provided one.
+        retry_after (Optional[float]): Seconds to wait before retrying, from the
+            `Retry-After` header of a rate-limited (429) response.
+        error_details (Optional[Any]): The `details` payload of a structured error
+            response, e.g. the cap and computed total of a max-total rejection or
+            the shortfall of an `insufficient_funds` error.
     
     **Error Types:**
         - "Invalid request" (400): Bad request format or missing required parameters
         - "Authentication error" (401): Authentication failed
+        - "Payment required" (402): Settlement was skipped in strict mode
         - "Authorization error" (403): Insufficient permissions
         - "Not found" (404): Resource not found
+        - "Conflict" (409): An identical settlement is already in flight or was
+          just completed (duplicate submission)
+        - "Rate limited" (429): Too many requests; see `retry_after`
         - "Client error" (4xx): Other client-side errors
         - "Server error" (5xx): Server-side errors
         - "Timeout": Request timed out (payment may have succeeded)
@@ -52,6 +70,8 @@ class SettlementServiceError(Exception):
             print(f"Error type: {e.error_type}")
             print(f"Status code: {e.status_code}")
             print(f"Detail: {e.error_detail}")
+            if e.error_code == "invalid_keypair":
+                ...
             # Convert to dict for API response
             error_dict = e.to_dict()
         ```
@@ -64,6 +84,9 @@ class SettlementServiceError(Exception):
         error_detail: Optional[str] = None,
         error_type: Optional[str] = None,
         response_body: Optional[Dict[str, Any]] = None,
+        error_code: Optional[str] = None,
+        retry_after: Optional[float] = None,
+        error_details: Optional[Any] = None,
     ):
         """
         Initialize settlement service error.
@@ -74,12 +97,18 @@ class SettlementServiceError(Exception):
             error_detail: Detailed error message from the service.
             error_type: Type/category of the error.
             response_body: Full response body if available.
+            error_code: Machine-readable error code, if available.
+            retry_after: Seconds to wait before retrying, if the service said so.
+            error_details: Structured error details from the service, if available.
         """
         super().__init__(message)
         self.status_code = status_code
         self.error_detail = error_detail
         self.error_type = error_type
         self.response_body = response_body
+        self.error_code = error_code
+        self.retry_after = retry_after
+        self.error_details = error_details
 
     def to_dict(self) -> Dict[str, Any]:
         """
@@ -87,11 +116,14 @@ class SettlementServiceError(Exception):
         
         Returns a dictionary representation of the error suitable for including
         in API responses. The dictionary includes error type, message, detail,
-        and status code.
+        error code, error details, retry delay, and status code.
         
         Returns:
             Dict with keys: "error" (error type), "message" (error message),
                 "detail" (detailed error message, if available),
+                "code" (machine-readable error code, if available),
+                "details" (structured error details, if available),
+                "retry_after" (seconds to wait before retrying, if available),
                 "status_code" (HTTP status code, if available).
         """
         result: Dict[str, Any] = {
@@ -100,11 +132,257 @@ class SettlementServiceError(Exception):
         }
         if self.error_detail:
             result["detail"] = self.error_detail
+        if self.error_code:
+            result["code"] = self.error_code
+        if self.error_details is not None:
+            result["details"] = self.error_details
+        if self.retry_after is not None:
+            result["retry_after"] = self.retry_after
         if self.status_code:
             result["status_code"] = self.status_code
         return result
 
 
+def _coerce_usage(usage: Any) -> Any:
+    """
+    Decode usage data that was sent as a JSON-encoded string.
+
+    Some callers double-encode usage (e.g. '{"input_tokens": 100}') instead of
+    sending an object. Strings are decoded until a non-string value is reached.
+    Only a decoded object or array is used; invalid JSON and JSON scalars such
+    as "123" or "null" are returned as the original string so the settlement
+    service can report them instead of receiving a bare number or null.
+
+    Args:
+        usage: Usage data as received from the caller.
+
+    Returns:
+        The decoded usage object, or the original value if it does not decode
+        to an object or array.
+    """
+    decoded = usage
+    while isinstance(decoded, str):
+        try:
+            decoded = json.loads(decoded)
+        except (json.JSONDecodeError, ValueError):
+            return usage
+    if isinstance(decoded, (dict, list)):
+        return decoded
+    return usage
+
+
+def _parse_retry_after(value: Optional[str]) -> Optional[float]:
+    """
+    Parse a `Retry-After` header given in seconds.
+
+    The HTTP-date form is not used by the settlement service and is ignored.
+
+    Args:
+        value: Raw header value, or None if the header was absent.
+
+    Returns:
+        The delay in seconds, or None if the header is missing or not a number.
+    """
+    if value is None:
+        return None
+    try:
+        seconds = float(value.strip())
+    except ValueError:
+        return None
+    if not math.isfinite(seconds) or seconds < 0:
+        return None
+    return seconds
+
+
+def _validate_fee_percent_override(fee_percent_override: Optional[float]) -> None:
+    """
+    Check a per-request processing fee override.
+
+    Args:
+        fee_percent_override: Fee as a fraction of the total, or None to use the
+            settlement service's configured fee.
+
+    Raises:
+        ValueError: If the override is not a finite number between 0.0 and 0.5.
+    """
+    if fee_percent_override is None:
+        return
+    if (
+        isinstance(fee_percent_override, bool)
+        or not isinstance(fee_percent_override, (int, float))
+        or not math.isfinite(fee_percent_override)
+        or not 0.0 <= fee_percent_override <= 0.5
+    ):
+        raise ValueError(
+            f"fee_percent_override must be a number between 0.0 and 0.5, got {fee_percent_override!r}"
+        )
+
+
+def _pricing_fields(
+    input_cost_per_million_usd: Optional[float],
+    output_cost_per_million_usd: Optional[float],
+    blended_cost_per_million_usd: Optional[float],
+    max_cost_per_million_usd: float = ATP_MAX_COST_PER_MILLION_USD,
+) -> Dict[str, float]:
+    """
+    Build the pricing portion of a calculate-payment or settle request body.
+
+    Exactly one pricing mode must be specified: either separate input/output
+    rates, or a single blended rate that the settlement service applies to
+    `total_tokens` (reported as `pricing.source == "blended"`). Every rate
+    must be a finite, non-negative number no greater than
+    `max_cost_per_million_usd`, which catches unit mistakes before they reach
+    the payer's wallet.
+
+    Args:
+        input_cost_per_million_usd: Cost per million input tokens in USD.
+        output_cost_per_million_usd: Cost per million output tokens in USD.
+        blended_cost_per_million_usd: Single cost per million total tokens in USD.
+        max_cost_per_million_usd: Largest accepted rate in USD per million tokens
+            (default: ATP_MAX_COST_PER_MILLION_USD).
+
+    Returns:
+        Dict with the pricing fields to include in the request payload.
+
+    Raises:
+        ValueError: If both pricing modes are given, neither is complete, or a
+            rate is not a number, negative, NaN, infinite, or above
+            `max_cost_per_million_usd`.
+    """
+    for name, rate in (
+        ("input_cost_per_million_usd", input_cost_per_million_usd),
+        ("output_cost_per_million_usd", output_cost_per_million_usd),
+        ("blended_cost_per_million_usd", blended_cost_per_million_usd),
+    ):
+        if rate is None:
+            continue
+        # bool is an int subclass; True must not be billed as 1 USD
+        if isinstance(rate, bool) or not isinstance(rate, (int, float)):
+            raise ValueError(
+                f"{name} must be a number, got {type(rate).__name__}"
+            )
+        if not math.isfinite(rate) or rate < 0:
+            raise ValueError(
+                f"{name} must be a finite, non-negative number, got {rate}"
+            )
+        if rate > max_cost_per_million_usd:
+            raise ValueError(
+                f"{name} of {rate} exceeds the maximum of "
+                f"{max_cost_per_million_usd} USD per million tokens "
+                "(raise max_cost_per_million_usd or ATP_MAX_COST_PER_MILLION_USD "
+                "if this is intended)"
+            )
+
+    has_split_rates = (
+        input_cost_per_million_usd is not None
+        or output_cost_per_million_usd is not None
+    )
+    if blended_cost_per_million_usd is not None:
+        if has_split_rates:
+            raise ValueError(
+                "Specify either input/output rates or blended_cost_per_million_usd, not both"
+            )
+        return {
+            "blended_cost_per_million_usd": blended_cost_per_million_usd,
+        }
+    if (
+        input_cost_per_million_usd is None
+        or output_cost_per_million_usd is None
+    ):
+        raise ValueError(
+            "input_cost_per_million_usd and output_cost_per_million_usd are both "
+            "required unless blended_cost_per_million_usd is set"
+        )
+    return {
+        "input_cost_per_million_usd": input_cost_per_million_usd,
+        "output_cost_per_million_usd": output_cost_per_million_usd,
+    }
+
+
+def _validate_recipients(
+    recipient_pubkey: Optional[str],
+    recipients: Optional[List[Dict[str, Any]]],
+) -> None:
+    """
+    Check the recipient fields of a settle request.
+
+    Exactly one of `recipient_pubkey` and `recipients` must be given. Each
+    entry of `recipients` needs a `pubkey` and a finite, positive `weight`.
+
+    Args:
+        recipient_pubkey: Single recipient public key, or None.
+        recipients: List of `{"pubkey": ..., "weight": ...}` dicts, or None.
+
+    Raises:
+        ValueError: If both or neither are given, `recipients` is empty, or an
+            entry has no pubkey or an invalid weight.
+    """
+    if (recipient_pubkey is None) == (recipients is None):
+        raise ValueError(
+            "Specify exactly one of recipient_pubkey or recipients"
+        )
+    if recipients is None:
+        return
+    if not recipients:
+        raise ValueError("recipients must not be empty")
+    for recipient in recipients:
+        weight = recipient.get("weight")
+        if not recipient.get("pubkey"):
+            raise ValueError(
+                f"Each recipient needs a pubkey, got {recipient!r}"
+            )
+        if (
+            isinstance(weight, bool)
+            or not isinstance(weight, (int, float))
+            or not math.isfinite(weight)
+            or weight <= 0
+        ):
+            raise ValueError(
+                f"Recipient weight must be a positive number, got {weight!r}"
+            )
+
+
+# Limits on settle() metadata, matching the bounds the settlement service enforces
+MAX_METADATA_ENTRIES = 16
+MAX_METADATA_BYTES = 2048
+
+
+def _validate_metadata(metadata: Optional[Dict[str, str]]) -> None:
+    """
+    Check the metadata map of a settle request before it is sent.
+
+    Metadata must map strings to strings, hold at most `MAX_METADATA_ENTRIES`
+    entries, and encode to at most `MAX_METADATA_BYTES` bytes of UTF-8 JSON.
+
+    Args:
+        metadata: Caller-supplied metadata, or None.
+
+    Raises:
+        ValueError: If metadata is not a dict, has a non-string key or value,
+            or exceeds the entry or size limit.
+    """
+    if metadata is None:
+        return
+    if not isinstance(metadata, dict):
+        raise ValueError(
+            f"metadata must be a dict of strings, got {type(metadata).__name__}"
+        )
+    for key, value in metadata.items():
+        if not isinstance(key, str) or not isinstance(value, str):
+            raise ValueError(
+                f"metadata keys and values must be strings, got {key!r}: {value!r}"
+            )
+    if len(metadata) > MAX_METADATA_ENTRIES:
+        raise ValueError(
+            f"metadata has {len(metadata)} entries, the maximum is {MAX_METADATA_ENTRIES}"
+        )
+    size = len(json.dumps(metadata, ensure_ascii=False).encode("utf-8"))
+    if size > MAX_METADATA_BYTES:
+        raise ValueError(
+            f"metadata is {size} bytes, the maximum is {MAX_METADATA_BYTES}"
+        )
+
+
 class SettlementServiceClient:
     """
     Client for ATP Settlement Service API.
@@ -142,6 +420,7 @@ class SettlementServiceClient:
     **Attributes:**
         base_url (str): Base URL of the settlement service (trailing slashes removed).
         timeout (float): Request timeout in seconds for all API calls.
+        api_token (Optional[str]): Bearer token sent in the `Authorization` header.
     
     **Example Usage:**
     
@@ -199,6 +478,8 @@ class SettlementServiceClient:
         self,
         base_url: str = ATP_SETTLEMENT_URL,
         timeout: Optional[float] = None,
+        api_token: Optional[str] = None,
+        max_cost_per_million_usd: Optional[float] = None,
     ):
         """
         Initialize the settlement service client.
@@ -208,9 +489,37 @@ class SettlementServiceClient:
             timeout: Request timeout in seconds (default: ATP_SETTLEMENT_TIMEOUT or 300.0).
                 Settlement operations may take longer due to blockchain confirmation times.
                 User-configurable - can be set via environment variable or passed directly.
+            api_token: Bearer token for settlement services that require authentication
+                (default: ATP_SETTLEMENT_API_TOKEN). When unset, no `Authorization`
+                header is sent.
+            max_cost_per_million_usd: Largest per-million-token rate in USD that
+                `calculate_payment` and `settle` accept (default:
+                ATP_MAX_COST_PER_MILLION_USD, read once at import). 0 rejects any
+                non-zero rate.
         """
         self.base_url = base_url.rstrip("/")
         self.timeout = timeout if timeout is not None else ATP_SETTLEMENT_TIMEOUT
+        self.api_token = (
+            api_token if api_token is not None else ATP_SETTLEMENT_API_TOKEN
+        )
+        self.max_cost_per_million_usd = (
+            max_cost_per_million_usd
+            if max_cost_per_million_usd is not None
+            else ATP_MAX_COST_PER_MILLION_USD
+        )
+
+    def _headers(self) -> Dict[str, str]:
+        """
+        Build the default headers sent with every settlement service request.
+
+        Returns:
+            Dict with an `Authorization: Bearer <token>` header when `api_token`
+            is set, otherwise an empty dict.
+        """
+        headers: Dict[str, str] = {}
+        if self.api_token:
+            headers["Authorization"] = f"Bearer {self.api_token}"
+        return headers
 
     def _extract_error_details(
         self, response: httpx.Response
@@ -223,6 +532,7 @@ class SettlementServiceClient:
         text responses, and multiple error field formats.
 
         **Supported Error Formats:**
+            - Structured: `{"error": {"code": "...", "message": "...", "details": ...}}`
             - FastAPI: `{"detail": "error message"}`
             - Generic: `{"error": "error message", "type": "error_type"}`
             - Message: `{"message": "error message"}`
@@ -236,12 +546,16 @@ class SettlementServiceClient:
                 - `status_code` (int): HTTP status code
                 - `error_detail` (Optional[str]): Detailed error message
                 - `error_type` (Optional[str]): Type/category of error
+                - `error_code` (Optional[str]): Machine-readable error code
+                - `error_details` (Optional[Any]): Structured error details
                 - `response_body` (Optional[Dict[str, Any]]): Full parsed response body
         """
         error_info: Dict[str, Any] = {
             "status_code": response.status_code,
             "error_detail": None,
             "error_type": None,
+            "error_code": None,
+            "error_details": None,
             "response_body": None,
         }
 
@@ -252,8 +566,14 @@ class SettlementServiceClient:
 
             # Extract error details from common response formats
             if isinstance(response_body, dict):
+                structured = response_body.get("error")
+                # Structured format: {"error": {"code": ..., "message": ..., "details": ...}}
+                if isinstance(structured, dict):
+                    error_info["error_detail"] = structured.get("message")
+                    error_info["error_code"] = structured.get("code")
+                    error_info["error_details"] = structured.get("details")
                 # FastAPI error format: {"detail": "error message"}
-                if "detail" in response_body:
+                elif "detail" in response_body:
                     error_info["error_detail"] = response_body["detail"]
                 # Alternative format: {"error": "error message"}
                 elif "error" in response_body:
@@ -288,8 +608,11 @@ class SettlementServiceClient:
         **Error Type Detection:**
             - 400: "Invalid request"
             - 401: "Authentication error"
+            - 402: "Payment required"
             - 403: "Authorization error"
             - 404: "Not found"
+            - 409: "Conflict"
+            - 429: "Rate limited" (with `retry_after` from the `Retry-After` header)
             - 4xx: "Client error"
             - 5xx: "Server error"
             - ReadTimeout: "Timeout" (with special message about payment possibly succeeding)
@@ -328,10 +651,16 @@ class SettlementServiceClient:
                     error_type = "Invalid request"
                 elif status_code == 401:
                     error_type = "Authentication error"
+                elif status_code == 402:
+                    error_type = "Payment required"
                 elif status_code == 403:
                     error_type = "Authorization error"
                 elif status_code == 404:
                     error_type = "Not found"
+                elif status_code == 409:
+                    error_type = "Conflict"
+                elif status_code == 429:
+                    error_type = "Rate limited"
             elif status_code >= 500:
                 error_type = "Server error"
             else:
@@ -359,6 +688,11 @@ class SettlementServiceClient:
                 error_detail=error_detail,
                 error_type=error_type,
                 response_body=error_info["response_body"],
+                error_code=error_info["error_code"],
+                error_details=error_info["error_details"],
+                retry_after=_parse_retry_after(
+                    response.headers.get("Retry-After")
+                ),
             )
         else:
             # Network/timeout errors without response
@@ -393,7 +727,9 @@ class SettlementServiceClient:
             )
 
     async def parse_usage(
-        self, usage_data: Dict[str, Any]
+        self,
+        usage_data: Union[Dict[str, Any], str],
+        request_id: Optional[str] = None,
     ) -> Dict[str, Optional[int]]:
         """
         Parse usage tokens from various API formats.
@@ -408,12 +744,16 @@ class SettlementServiceClient:
             - Anthropic: `input_tokens`, `output_tokens`, `total_tokens`
             - Google/Gemini: `promptTokenCount`, `candidatesTokenCount`, `totalTokenCount`
             - Cohere: `tokens`, `input_tokens`, `output_tokens`
+            - Mistral: `prompt_tokens`, `completion_tokens`, `total_tokens` under `usage`
+            - AI21: `promptTokens`, `completionTokens`, `totalTokens` under `usage`
             - Nested: `usage.usage`, `meta.usage`, `statistics`
 
         **Args:**
             usage_data: Usage data in any supported format. Can be the entire response
                 body or just the usage portion. The service handles nested structures
-                automatically.
+                automatically. A JSON-encoded string is decoded before sending.
+            request_id: Optional correlation id sent as the `X-Request-Id` header.
+                Default: None.
 
         **Returns:**
             Dict with normalized keys:
@@ -448,11 +788,16 @@ class SettlementServiceClient:
         """
         try:
             async with httpx.AsyncClient(
-                timeout=self.timeout
+                timeout=self.timeout, headers=self._headers()
             ) as client:
                 response = await client.post(
                     f"{self.base_url}/v1/settlement/parse-usage",
-                    json={"usage_data": usage_data},
+                    json={"usage_data": _coerce_usage(usage_data)},
+                    headers=(
+                        {"X-Request-Id": request_id}
+                        if request_id is not None
+                        else None
+                    ),
                 )
                 response.raise_for_status()
                 return response.json()
@@ -470,10 +815,13 @@ class SettlementServiceClient:
 
     async def calculate_payment(
         self,
-        usage: Dict[str, Any],
-        input_cost_per_million_usd: float,
-        output_cost_per_million_usd: float,
-        payment_token: str = "SOL",
+        usage: Union[Dict[str, Any], str],
+        input_cost_per_million_usd: Optional[float] = None,
+        output_cost_per_million_usd: Optional[float] = None,
+        payment_token: Optional[str] = None,
+        blended_cost_per_million_usd: Optional[float] = None,
+        fee_mode: Optional[str] = None,
+        fee_percent_override: Optional[float] = None,
     ) -> Dict[str, Any]:
         """
         Calculate payment amounts from usage data.
@@ -484,7 +832,8 @@ class SettlementServiceClient:
 
         **Calculation Process:**
             1. Parses usage tokens from the provided usage data
-            2. Calculates USD cost: (input_tokens / 1M) * input_rate + (output_tokens / 1M) * output_rate
+            2. Calculates USD cost: (input_tokens / 1M) * input_rate + (output_tokens / 1M) * output_rate,
+               or (total_tokens / 1M) * blended_rate when a blended rate is given
             3. Fetches current token price from price oracle
             4. Converts USD cost to token amount
             5. Calculates split: treasury fee (5% default) and agent amount (95% default)
@@ -492,20 +841,48 @@ class SettlementServiceClient:
         **Args:**
             usage: Usage data containing token counts. Supports same formats as
                 `parse_usage` method. Can be raw usage data or already parsed.
+                A JSON-encoded string is decoded before sending.
             input_cost_per_million_usd: Cost per million input tokens in USD.
+                Required together with `output_cost_per_million_usd` unless
+                `blended_cost_per_million_usd` is set.
             output_cost_per_million_usd: Cost per million output tokens in USD.
             payment_token: Token to use for payment. Must be "SOL" or "USDC".
-                Default: "SOL".
+                Default: None (omitted, so the settlement service applies its
+                configured DEFAULT_PAYMENT_TOKEN, "SOL" unless changed).
+            blended_cost_per_million_usd: Single cost per million total tokens in USD.
+                Mutually exclusive with the input/output rates. The service reports
+                this mode as `pricing.source == "blended"`.
+            fee_mode: "inclusive" (fee deducted from the total) or "exclusive" (fee
+                added on top of the recipient amount). Default: None (service
+                default, "inclusive").
+            fee_percent_override: Optional processing fee fraction for this request
+                only, replacing the service's configured fee. Must be between 0.0
+                and 0.5. Forwarded only; a service without overrides applies its
+                configured fee. Default: None.
 
         **Returns:**
             Dict with payment calculation details:
                 - `status` (str): "calculated" or "skipped" (if zero cost)
-                - `pricing` (dict): Pricing information with token counts and costs
+                - `reason` (str, optional): Why the calculation was skipped, one of
+                  the :class:`atp.schemas.SettlementSkipReason` values
+                - `parse_succeeded` (bool): False if the usage format was not
+                  recognized and the token counts fell back to zero
+                - `source_format` (str, optional): Usage format that was detected
+                  (e.g. "openai", "anthropic")
+                - `pricing` (dict): Pricing information with token counts and costs,
+                  including `decimals` applied when converting to token units
                 - `payment_amounts` (dict, optional): Payment amounts in token units
                 - `token_price_usd` (float, optional): Current token price in USD
+                - `price_is_fallback` (bool, optional): True if the price fetch failed
+                  and the service's configured fallback price was used instead
+                - `token_info` (dict, optional): Token the amounts are denominated in:
+                  `symbol`, `decimals` (9 for SOL, 6 for USDC), and `mint_address`
+                  (None for native SOL)
 
         **Raises:**
             SettlementServiceError: If the settlement service returns an error.
+            ValueError: If not exactly one pricing mode is specified, a rate is
+                negative or not finite, or fee_percent_override is out of range.
 
         **Example:**
             ```python
@@ -534,18 +911,32 @@ class SettlementServiceClient:
             # }
             ```
         """
+        pricing_fields = _pricing_fields(
+            input_cost_per_million_usd,
+            output_cost_per_million_usd,
+            blended_cost_per_million_usd,
+            max_cost_per_million_usd=self.max_cost_per_million_usd,
+        )
+        _validate_fee_percent_override(fee_percent_override)
+
+        payload: Dict[str, Any] = {
+            "usage": _coerce_usage(usage),
+            **pricing_fields,
+        }
+        if payment_token is not None:
+            payload["payment_token"] = payment_token
+        if fee_mode is not None:
+            payload["fee_mode"] = fee_mode
+        if fee_percent_override is not None:
+            payload["fee_percent_override"] = fee_percent_override
+
         try:
             async with httpx.AsyncClient(
-                timeout=self.timeout
+                timeout=self.timeout, headers=self._headers()
             ) as client:
                 response = await client.post(
                     f"{self.base_url}/v1/settlement/calculate-payment",
-                    json={
-                        "usage": usage,
-                        "input_cost_per_million_usd": input_cost_per_million_usd,
-                        "output_cost_per_million_usd": output_cost_per_million_usd,
-                        "payment_token": payment_token,
-                    },
+                    json=payload,
                 )
                 response.raise_for_status()
                 return response.json()
@@ -561,16 +952,112 @@ class SettlementServiceClient:
                 error_type="Unexpected error",
             )
 
+    async def _enforce_spend_caps(
+        self,
+        usage: Union[Dict[str, Any], str],
+        input_cost_per_million_usd: Optional[float],
+        output_cost_per_million_usd: Optional[float],
+        blended_cost_per_million_usd: Optional[float],
+        payment_token: Optional[str],
+        fee_mode: Optional[str],
+        fee_percent_override: Optional[float],
+        max_total_usd: Optional[float],
+        max_total_token: Optional[float],
+    ) -> None:
+        """
+        Reject a settlement whose quoted total exceeds the caller's ceilings.
+
+        The settlement service does not enforce spend ceilings itself, so `settle`
+        calls this first. It quotes the settlement through `calculate_payment` with
+        the same usage and pricing and compares `pricing.usd_cost` to
+        `max_total_usd` and `payment_amounts.total_amount_token` to
+        `max_total_token`. A quote that omits a capped total fails closed.
+
+        **Raises:**
+            SettlementServiceError: With error_code "max_total_exceeded" (and the
+                quoted total and cap in `error_details`) if a ceiling is exceeded,
+                or "max_total_unverified" if the quote does not report a capped
+                total. Errors from the quote itself propagate unchanged.
+        """
+        quote = await self.calculate_payment(
+            usage=usage,
+            input_cost_per_million_usd=input_cost_per_million_usd,
+            output_cost_per_million_usd=output_cost_per_million_usd,
+            payment_token=payment_token,
+            blended_cost_per_million_usd=blended_cost_per_million_usd,
+            fee_mode=fee_mode,
+            fee_percent_override=fee_percent_override,
+        )
+        if quote.get("status") == "skipped":
+            # Zero-cost settlements move no funds
+            return
+
+        for field, total, cap in (
+            (
+                "max_total_usd",
+                (quote.get("pricing") or {}).get("usd_cost"),
+                max_total_usd,
+            ),
+            (
+                "max_total_token",
+                (quote.get("payment_amounts") or {}).get(
+                    "total_amount_token"
+                ),
+                max_total_token,
+            ),
+        ):
+            if cap is None:
+                continue
+            if total is None:
+                raise SettlementServiceError(
+                    message=(
+                        f"Cannot enforce {field}: calculate-payment did not report "
+                        "the total; refusing to settle"
+                    ),
+                    error_type="Spend limit exceeded",
+                    error_code="max_total_unverified",
+                )
+            if total > cap:
+                raise SettlementServiceError(
+                    message=(
+                        f"Settlement total {total} exceeds {field} of {cap}; "
+                        "nothing was signed"
+                    ),
+                    error_type="Spend limit exceeded",
+                    error_code="max_total_exceeded",
+                    error_details={"field": field, "total": total, "cap": cap},
+                )
+
     async def settle(
         self,
-        private_key: str,
-        usage: Dict[str, Any],
-        input_cost_per_million_usd: float,
-        output_cost_per_million_usd: float,
-        recipient_pubkey: str,
-        payment_token: str = "SOL",
+        private_key: Optional[str],
+        usage: Union[Dict[str, Any], str],
+        input_cost_per_million_usd: Optional[float] = None,
+        output_cost_per_million_usd: Optional[float] = None,
+        recipient_pubkey: Optional[str] = None,
+        payment_token: Optional[str] = None,
         skip_preflight: bool = False,
         commitment: str = "confirmed",
+        blended_cost_per_million_usd: Optional[float] = None,
+        metadata: Optional[Dict[str, str]] = None,
+        fee_recipient: Optional[str] = None,
+        max_total_usd: Optional[float] = None,
+        max_total_token: Optional[float] = None,
+        blockhash_commitment: Optional[str] = None,
+        referral_pubkey: Optional[str] = None,
+        referral_share_bps: Optional[int] = None,
+        strict: bool = False,
+        fee_mode: Optional[str] = None,
+        estimate_only: bool = False,
+        rpc_url_override: Optional[str] = None,
+        max_price_age_secs: Optional[float] = None,
+        idempotency_key: Optional[str] = None,
+        recipients: Optional[List[Dict[str, Any]]] = None,
+        skip_balance_check: bool = False,
+        signer_id: Optional[str] = None,
+        callback_url: Optional[str] = None,
+        request_id: Optional[str] = None,
+        fee_percent_override: Optional[float] = None,
     ) -> Dict[str, Any]:
         """
         Execute a settlement payment on Solana blockchain.
@@ -582,7 +1069,7 @@ class SettlementServiceClient:
         **Settlement Flow:**
             1. Parses usage tokens from the provided usage data
             2. Calculates payment amounts based on pricing rates
-            3. Fetches current token price (currently supports SOL only)
+            3. Fetches current token price (SOL or USDC)
             4. Creates a split payment transaction (treasury fee + recipient payment)
             5. Signs the transaction with the provided private key
             6. Sends the transaction to Solana network
@@ -598,20 +1085,30 @@ class SettlementServiceClient:
             - Private key is used only in-memory for transaction signing
             - No key material is logged or persisted
             - Transaction is executed on-chain with full transparency
-            - The treasury pubkey is configured on the settlement service and cannot be overridden
+            - The treasury pubkey is configured on the settlement service and cannot be overridden;
+              `fee_recipient` only redirects the fee transfer of a single settlement
 
         **Args:**
             private_key: Solana wallet private key. Can be in JSON array format
                 (e.g., "[1,2,3,...64 bytes...]") or base58 encoded string.
                 Must be 32 or 64 bytes. WARNING: This is custodial-like behavior.
+                May be None only when `signer_id` is given, and must be None when
+                `estimate_only` is True.
             usage: Usage data containing token counts. Supports same formats as
                 `parse_usage` method. Can be raw usage data or already parsed.
+                A JSON-encoded string is decoded before sending.
             input_cost_per_million_usd: Cost per million input tokens in USD.
+                Required unless `blended_cost_per_million_usd` is set. Default: None.
             output_cost_per_million_usd: Cost per million output tokens in USD.
+                Required unless `blended_cost_per_million_usd` is set. Default: None.
             recipient_pubkey: Solana public key of the recipient wallet (base58 encoded).
-                This wallet receives the net payment after fees.
-            payment_token: Token to use for payment. Currently only "SOL" is supported
-                for automatic settlement. Default: "SOL".
+                This wallet receives the net payment after fees. Required unless
+                `recipients` is given. Default: None.
+            payment_token: Token to use for payment. Must be "SOL" or "USDC". USDC is
+                settled as SPL token transfers between associated token accounts; the
+                recipient's account is created by the service if missing. Default:
+                None (omitted, so the settlement service applies its configured
+                DEFAULT_PAYMENT_TOKEN, "SOL" unless changed).
             skip_preflight: Whether to skip preflight simulation. Setting to True
                 can speed up transactions but may result in failed transactions.
                 Default: False.
@@ -620,18 +1117,125 @@ class SettlementServiceClient:
                 - "confirmed": Recommended default, confirmed by cluster
                 - "finalized": Slowest, but cannot be rolled back
                 Default: "confirmed".
+            blended_cost_per_million_usd: Single cost per million total tokens in USD,
+                used instead of the input/output rates. Default: None.
+            metadata: Optional string-to-string map (e.g. invoice or order IDs) stored
+                with the settlement record and echoed back in the response. It is
+                never written on-chain. At most `MAX_METADATA_ENTRIES` entries and
+                `MAX_METADATA_BYTES` bytes of JSON. Default: None.
+            fee_recipient: Optional Solana public key to receive the processing fee
+                for this settlement instead of the configured treasury (e.g. a partner
+                or referral wallet). Forwarded to the service; one without fee
+                routing ignores it and pays the treasury, so confirm the destination
+                in `payment` before relying on it. Default: None.
+            max_total_usd: Optional ceiling on the settlement cost in USD. Enforced
+                by this client: when a ceiling is set, the settlement is first quoted
+                with `calculate_payment` and nothing is sent to `/settle` if the
+                quoted `pricing.usd_cost` exceeds it. Default: None.
+            max_total_token: Optional ceiling on the total amount in `payment_token`
+                units (e.g. SOL), checked against the quoted
+                `payment_amounts.total_amount_token`. The settlement prices the token
+                again, so the amount actually paid can differ from the quote by any
+                price movement between the two calls. Default: None.
+            blockhash_commitment: Optional commitment level used only when fetching the
+                recent blockhash, independent of `commitment`. "finalized" gives a
+                slightly older but more stable blockhash under congestion.
+                Default: None (service default).
+            referral_pubkey: Optional Solana public key of a referral partner to
+                receive `referral_share_bps` of the processing fee in the same
+                transaction. Must be given together with `referral_share_bps`. The
+                pair is forwarded as-is; a service without referral splits ignores it
+                and the treasury keeps the whole fee. Default: None.
+            referral_share_bps: Share of the processing fee for `referral_pubkey`, in
+                basis points (0-10000). Default: None.
+            strict: If True, asks the service to return a settlement skipped for zero
+                or below-minimum cost as HTTP 402 (a SettlementServiceError with
+                error_type "Payment required") instead of status "skipped". Forwarded
+                only; a service without strict mode ignores it and still answers
+                "skipped", so keep checking `status`. Default: False.
+            fee_mode: How the processing fee relates to the priced amount:
+                - "inclusive": Fee is deducted from the total (current behavior)
+                - "exclusive": Recipient receives the full priced amount and the fee
+                  is added on top, paid by the payer
+                Default: None (service default, "inclusive").
+            estimate_only: If True, the service computes the full payment details
+                (treasury/recipient breakdown and fee) and returns status "estimated"
+                without any chain interaction or signature. `private_key` must be None,
+                so no key material is sent for an estimate. Default: False.
+            rpc_url_override: Optional Solana RPC URL to use for this settlement only,
+                instead of the service's configured RPC. Only honored for trusted
+                (admin-authenticated) callers; otherwise the service rejects the
+                request. Intended for local-validator and RPC-provider canary testing.
+                Default: None.
+            max_price_age_secs: Optional freshness requirement for the token price.
+                If the only cached price is older than this, the service refreshes it
+                (or defers the settlement if the refresh fails). The price age used is
+                reported in `pricing`. Default: None (service cache TTL applies).
+            idempotency_key: Optional client-chosen key, forwarded as the
+                `Idempotency-Key` header for settlement services that deduplicate on
+                it. The current service does not, so a retry after a timeout can pay
+                twice; check `transaction_signature` on-chain before retrying.
+                Default: None.
+            recipients: Optional list of recipients for a multi-recipient split, each a
+                dict with `pubkey` (base58) and a positive `weight`. The service
+                normalizes the weights, pays each recipient its share of the net amount
+                in the same transaction as the treasury fee, and assigns rounding
+                remainders to the first recipient. Mutually exclusive with
+                `recipient_pubkey`, which must then be None. Default: None.
+            skip_balance_check: If True, skips the service's pre-flight check that the
+                payer balance covers the transfers plus the estimated network fee. By
+                default an under-funded payer gets a structured `insufficient_funds`
+                error with the shortfall before any transaction is built. Default: False.
+            signer_id: Optional identifier of a payer keypair pre-loaded on the
+                settlement service (from its KEYPAIR_DIR). When given, the service signs
+                with that keypair and `private_key` must be None, so no key material
+                leaves the caller. Unknown ids are rejected by the service. Default: None.
+            callback_url: Optional URL the settlement service POSTs the settle response
+                to once the settlement finishes (success or failure), with retries and a
+                short timeout. Requests carry an HMAC signature header computed with the
+                service's WEBHOOK_SECRET so receivers can verify them. The HTTP response
+                to this call is not delayed by the callback. Default: None.
+            request_id: Optional correlation id sent as the `X-Request-Id` header. The
+                settlement service tags its logs for this settlement (including the RPC
+                submission) with the id and echoes it back, so a caller's request can be
+                traced through the service. Default: None (the service assigns one).
+            fee_percent_override: Optional processing fee fraction for this settlement
+                only (e.g. 0.03 for 3%), to replace the service's configured
+                `settlement_fee_percent`. Must be between 0.0 and 0.5. Services that do
+                not support overrides ignore it and charge the configured fee; the
+                amounts in `payment` show which fee was applied. Default: None.
 
         **Returns:**
             Dict with payment details:
-                - `status` (str): "paid" if successful, "skipped" if zero cost
+                - `status` (str): "paid" if successful, "skipped" if zero cost,
+                  "submitted_unconfirmed" if the transaction was sent but confirmation
+                  timed out, "estimated" for `estimate_only` requests
+                  (see :class:`atp.schemas.SettlementStatus`)
+                - `reason` (str, optional): Why the settlement was skipped, one of
+                  the :class:`atp.schemas.SettlementSkipReason` values
+                - `settlement_id` (str): Identifier the service assigns to every
+                  settlement, including skipped and failed ones; use it to correlate
+                  logs and webhooks
                 - `transaction_signature` (str, optional): Solana transaction signature
-                - `pricing` (dict): Complete cost breakdown
+                - `pricing` (dict): Complete cost breakdown, including `decimals`
+                  applied when converting to token units
+                - `price_is_fallback` (bool, optional): True if the amounts were computed
+                  from the service's configured fallback price rather than a live quote
                 - `payment` (dict, optional): Payment details including:
                     - `total_amount_lamports` (int): Total payment in lamports
                     - `total_amount_sol` (float): Total payment in SOL
                     - `total_amount_usd` (float): Total payment in USD
                     - `treasury` (dict): Treasury payment details
+                    - `fee_recipient` (dict, optional): Fee destination details when
+                      `fee_recipient` overrides the treasury
+                    - `referral` (dict, optional): Referral payment details when
+                      `referral_pubkey` is set
                     - `recipient` (dict): Recipient payment details
+                    - `fee_mode` (str): Fee mode that was applied
+                    - `effective_fee_bps` (int): Processing fee actually charged, in
+                      basis points of the total
+                    - `recent_blockhash` (str): Blockhash the transaction was built
+                      with, so the signature can be recomputed independently
 
         **Raises:**
             SettlementServiceError: If the settlement service returns an error.
@@ -640,6 +1244,17 @@ class SettlementServiceClient:
                 - Insufficient funds
                 - Network errors
                 - Transaction failures
+                Also raised with error_code "max_total_exceeded" when the quoted
+                total exceeds `max_total_usd` or `max_total_token`.
+            ValueError: If not exactly one pricing mode is specified, a rate is
+                negative or not finite, the referral fields are incomplete or
+                referral_share_bps is not an integer in 0-10000, neither or both of private_key and signer_id are
+                given (outside estimate_only mode), private_key is given together
+                with estimate_only, not exactly one of recipient_pubkey and
+                recipients is given (or a recipient entry has no pubkey or a
+                non-positive weight),
+                fee_percent_override is outside 0.0-0.5, or metadata is not a
+                string-to-string map within the entry and size limits.
 
         **Example:**
             ```python
@@ -668,27 +1283,114 @@ class SettlementServiceClient:
         **Note:**
             The treasury_pubkey is automatically set from the SWARMS_TREASURY_PUBKEY
             environment variable on the settlement service and cannot be overridden.
+            Use `fee_recipient` to route the fee of one settlement elsewhere.
             Settlement operations may take time due to blockchain confirmation. Increase
             the client timeout if you experience timeout errors even when payments succeed.
         """
+        if private_key is not None and signer_id is not None:
+            raise ValueError(
+                "Specify either private_key or signer_id, not both"
+            )
+        if private_key is None and signer_id is None and not estimate_only:
+            raise ValueError(
+                "private_key or signer_id is required unless estimate_only is True"
+            )
+        if estimate_only and private_key is not None:
+            raise ValueError(
+                "private_key must not be sent with estimate_only; pass None"
+            )
+        _validate_recipients(recipient_pubkey, recipients)
+        _validate_metadata(metadata)
+        _validate_fee_percent_override(fee_percent_override)
+        if (referral_pubkey is None) != (referral_share_bps is None):
+            raise ValueError(
+                "referral_pubkey and referral_share_bps must be provided together"
+            )
+        if referral_share_bps is not None and (
+            isinstance(referral_share_bps, bool)
+            or not isinstance(referral_share_bps, int)
+            or not 0 <= referral_share_bps <= 10_000
+        ):
+            raise ValueError(
+                f"referral_share_bps must be an integer between 0 and 10000, got {referral_share_bps!r}"
+            )
+
+        pricing_fields = _pricing_fields(
+            input_cost_per_million_usd,
+            output_cost_per_million_usd,
+            blended_cost_per_million_usd,
+            max_cost_per_million_usd=self.max_cost_per_million_usd,
+        )
+        if max_total_usd is not None or max_total_token is not None:
+            await self._enforce_spend_caps(
+                usage=usage,
+                input_cost_per_million_usd=input_cost_per_million_usd,
+                output_cost_per_million_usd=output_cost_per_million_usd,
+                blended_cost_per_million_usd=blended_cost_per_million_usd,
+                payment_token=payment_token,
+                fee_mode=fee_mode,
+                fee_percent_override=fee_percent_override,
+                max_total_usd=max_total_usd,
+                max_total_token=max_total_token,
+            )
+
         try:
             async with httpx.AsyncClient(
-                timeout=self.timeout
+                timeout=self.timeout, headers=self._headers()
             ) as client:
                 payload: Dict[str, Any] = {
-                    "private_key": private_key,
-                    "usage": usage,
-                    "input_cost_per_million_usd": input_cost_per_million_usd,
-                    "output_cost_per_million_usd": output_cost_per_million_usd,
-                    "recipient_pubkey": recipient_pubkey,
-                    "payment_token": payment_token,
+                    "usage": _coerce_usage(usage),
+                    **pricing_fields,
                     "skip_preflight": skip_preflight,
                     "commitment": commitment,
                 }
+                if payment_token is not None:
+                    payload["payment_token"] = payment_token
+                if private_key is not None:
+                    payload["private_key"] = private_key
+                if recipient_pubkey is not None:
+                    payload["recipient_pubkey"] = recipient_pubkey
+                if metadata is not None:
+                    payload["metadata"] = metadata
+                if fee_recipient is not None:
+                    payload["fee_recipient"] = fee_recipient
+                if blockhash_commitment is not None:
+                    payload["blockhash_commitment"] = blockhash_commitment
+                if referral_pubkey is not None:
+                    payload["referral_pubkey"] = referral_pubkey
+                if referral_share_bps is not None:
+                    payload["referral_share_bps"] = referral_share_bps
+                if strict:
+                    payload["strict"] = True
+                if fee_mode is not None:
+                    payload["fee_mode"] = fee_mode
+                if estimate_only:
+                    payload["estimate_only"] = True
+                if rpc_url_override is not None:
+                    payload["rpc_url_override"] = rpc_url_override
+                if max_price_age_secs is not None:
+                    payload["max_price_age_secs"] = max_price_age_secs
+                if recipients is not None:
+                    payload["recipients"] = recipients
+                if skip_balance_check:
+                    payload["skip_balance_check"] = True
+                if signer_id is not None:
+                    payload["signer_id"] = signer_id
+                if callback_url is not None:
+                    payload["callback_url"] = callback_url
+                if fee_percent_override is not None:
+                    payload["fee_percent_override"] = fee_percent_override
+
+                headers = {}
+                if idempotency_key is not None:
+                    headers["Idempotency-Key"] = idempotency_key
+                if request_id is not None:
+                    headers["X-Request-Id"] = request_id
 
                 response = await client.post(
                     f"{self.base_url}/v1/settlement/settle",
                     json=payload,
+                    headers=headers,
                 )
                 response.raise_for_status()
                 return response.json()
@@ -735,7 +1437,7 @@ class SettlementServiceClient:
         """
         try:
             async with httpx.AsyncClient(
-                timeout=self.timeout
+                timeout=self.timeout, headers=self._headers()
             ) as client:
                 response = await client.get(f"{self.base_url}/health")
                 response.raise_for_status()
@@ -0,0 +1,65 @@
+"""
+Unit tests for ATPClient argument handling.
+
+The settlement service client is replaced with an in-process fake, so these
+tests need no network access:
+
+    pytest tests/test_client.py
+"""
+
+import asyncio
+
+import pytest
+
+from atp.client import ATPClient
+from atp.settlement_client import SettlementServiceClient
+
+SETTLE_ARGS = {
+    "usage": {"input_tokens": 1000, "output_tokens": 500},
+    "input_cost_per_million_usd": 10.0,
+    "output_cost_per_million_usd": 30.0,
+    "recipient_pubkey": "RecipientPublicKeyHere",
+}
+
+
+@pytest.fixture
+def sent_keys(monkeypatch):
+    keys = []
+
+    async def fake_settle(self, **kwargs):
+        keys.append((kwargs["private_key"], kwargs["signer_id"]))
+        return {"status": "paid"}
+
+    monkeypatch.setattr(SettlementServiceClient, "settle", fake_settle)
+    return keys
+
+
+def test_settle_rejects_explicit_key_with_signer_id(sent_keys):
+    client = ATPClient()
+
+    with pytest.raises(ValueError, match="not both"):
+        asyncio.run(
+            client.settle(
+                **SETTLE_ARGS,
+                wallet_private_key="[1,2,3]",
+                signer_id="hot-wallet-1",
+            )
+        )
+
+    assert sent_keys == []
+
+
+def test_settle_with_signer_id_ignores_default_key(sent_keys):
+    client = ATPClient(wallet_private_key="[1,2,3]")
+
+    asyncio.run(client.settle(**SETTLE_ARGS, signer_id="hot-wallet-1"))
+
+    assert sent_keys == [(None, "hot-wallet-1")]
+
+
+def test_settle_sends_default_key_without_signer_id(sent_keys):
+    client = ATPClient(wallet_private_key="[1,2,3]")
+
+    asyncio.run(client.settle(**SETTLE_ARGS))
+
+    assert sent_keys == [("[1,2,3]", None)]
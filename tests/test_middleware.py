@@ -0,0 +1,206 @@
+"""
+Unit tests for ATPSettlementMiddleware request-id handling.
+
+The settlement service is replaced with in-process fakes, so these tests need
+no network access:
+
+    pytest tests/test_middleware.py
+"""
+
+import pytest
+from fastapi import FastAPI, HTTPException
+from fastapi.testclient import TestClient
+
+from atp.middleware import ATPSettlementMiddleware
+from atp.settlement_client import (
+    SettlementServiceClient,
+    SettlementServiceError,
+)
+
+WALLET_HEADER = {"x-wallet-private-key": "[1,2,3]"}
+
+
+@pytest.fixture
+def settlement_calls(monkeypatch):
+    calls = []
+
+    async def fake_parse_usage(self, usage_data, request_id=None):
+        calls.append(("parse_usage", request_id))
+        return {
+            "input_tokens": 10,
+            "output_tokens": 5,
+            "total_tokens": 15,
+        }
+
+    async def fake_settle(self, **kwargs):
+        calls.append(("settle", kwargs.get("request_id")))
+        return {
+            "status": "paid",
+            "transaction_signature": "5" * 64,
+            "settlement_id": "stl_test",
+        }
+
+    monkeypatch.setattr(
+        SettlementServiceClient, "parse_usage", fake_parse_usage
+    )
+    monkeypatch.setattr(SettlementServiceClient, "settle", fake_settle)
+    return calls
+
+
+def _make_client(**middleware_options):
+    app = FastAPI()
+    app.add_middleware(
+        ATPSettlementMiddleware,
+        allowed_endpoints=["/v1/chat", "/v1/fail"],
+        input_cost_per_million_usd=10.0,
+        output_cost_per_million_usd=30.0,
+        recipient_pubkey="RecipientPublicKeyHere",
+        **middleware_options,
+    )
+
+    @app.post("/v1/chat")
+    async def chat():
+        return {
+            "output": "hello",
+            "usage": {"input_tokens": 10, "output_tokens": 5},
+        }
+
+    @app.post("/v1/fail")
+    async def fail():
+        raise HTTPException(status_code=400, detail="bad request")
+
+    # Exceptions escaping the middleware would surface as a bare 500, so the
+    # tests assert on the responses a real caller receives
+    return TestClient(app, raise_server_exceptions=False)
+
+
+@pytest.fixture
+def client():
+    return _make_client()
+
+
+def test_incoming_request_id_round_trips(client, settlement_calls):
+    response = client.post(
+        "/v1/chat",
+        headers={**WALLET_HEADER, "X-Request-Id": "req-123.abc"},
+    )
+
+    assert response.status_code == 200
+    assert response.headers["x-request-id"] == "req-123.abc"
+    assert settlement_calls == [
+        ("parse_usage", "req-123.abc"),
+        ("settle", "req-123.abc"),
+    ]
+
+
+def test_request_id_generated_when_missing(client, settlement_calls):
+    response = client.post("/v1/chat", headers=WALLET_HEADER)
+
+    request_id = response.headers["x-request-id"]
+    assert len(request_id) == 32
+    assert settlement_calls[-1] == ("settle", request_id)
+
+
+@pytest.mark.parametrize(
+    "incoming", ["has spaces", "x" * 129, "semi;colon"]
+)
+def test_unsafe_request_id_is_replaced(
+    client, settlement_calls, incoming
+):
+    response = client.post(
+        "/v1/chat",
+        headers={**WALLET_HEADER, "X-Request-Id": incoming},
+    )
+
+    request_id = response.headers["x-request-id"]
+    assert request_id != incoming
+    assert len(request_id) == 32
+
+
+def test_request_id_on_endpoint_error_passthrough(
+    client, settlement_calls
+):
+    response = client.post(
+        "/v1/fail",
+        headers={**WALLET_HEADER, "X-Request-Id": "req-err"},
+    )
+
+    assert response.status_code == 400
+    assert response.headers["x-request-id"] == "req-err"
+    assert settlement_calls == []
+
+
+def test_missing_wallet_returns_402_with_request_id(client, settlement_calls):
+    response = client.post("/v1/chat", headers={"X-Request-Id": "req-402"})
+
+    assert response.status_code == 402
+    assert response.headers["x-request-id"] == "req-402"
+    assert "Missing wallet private key" in response.json()["detail"]
+    assert settlement_calls == []
+
+
+def test_missing_usage_returns_422_with_request_id(
+    client, settlement_calls, monkeypatch
+):
+    async def no_usage(self, usage_data, request_id=None):
+        return {
+            "input_tokens": None,
+            "output_tokens": None,
+            "total_tokens": None,
+        }
+
+    monkeypatch.setattr(SettlementServiceClient, "parse_usage", no_usage)
+
+    response = client.post(
+        "/v1/chat",
+        headers={**WALLET_HEADER, "X-Request-Id": "req-422"},
+    )
+
+    assert response.status_code == 422
+    assert response.headers["x-request-id"] == "req-422"
+    assert "token usage" in response.json()["detail"]
+
+
+def test_rate_limited_settlement_forwards_retry_after(
+    settlement_calls, monkeypatch
+):
+    async def rate_limited_settle(self, **kwargs):
+        raise SettlementServiceError(
+            message="Settlement service settle failed: Too many requests",
+            status_code=429,
+            error_detail="Too many requests",
+            error_type="Rate limited",
+            retry_after=2.5,
+        )
+
+    monkeypatch.setattr(SettlementServiceClient, "settle", rate_limited_settle)
+    client = _make_client(fail_on_settlement_error=True)
+
+    response = client.post(
+        "/v1/chat",
+        headers={**WALLET_HEADER, "X-Request-Id": "req-429"},
+    )
+
+    assert response.status_code == 429
+    assert response.headers["retry-after"] == "3"
+    assert response.headers["x-request-id"] == "req-429"
+    assert response.json() == {"detail": "Too many requests"}
+
+
+def test_unexpected_settlement_error_returns_500_with_request_id(
+    settlement_calls, monkeypatch
+):
+    async def broken_settle(self, **kwargs):
+        raise RuntimeError("boom")
+
+    monkeypatch.setattr(SettlementServiceClient, "settle", broken_settle)
+    client = _make_client(fail_on_settlement_error=True)
+
+    response = client.post(
+        "/v1/chat",
+        headers={**WALLET_HEADER, "X-Request-Id": "req-500"},
+    )
+
+    assert response.status_code == 500
+    assert response.headers["x-request-id"] == "req-500"
+    assert "retry-after" not in response.headers
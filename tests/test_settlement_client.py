@@ -0,0 +1,503 @@
+"""
+Unit tests for SettlementServiceClient.
+
+HTTP calls are served by ``httpx.MockTransport``, so these tests need no
+running settlement service:
+
+    pytest tests/test_settlement_client.py
+"""
+
+import asyncio
+import importlib
+import json
+
+import httpx
+import pytest
+
+from atp import config
+from atp.config import ATP_MAX_COST_PER_MILLION_USD
+from atp.settlement_client import (
+    MAX_METADATA_BYTES,
+    MAX_METADATA_ENTRIES,
+    SettlementServiceClient,
+    SettlementServiceError,
+    _coerce_usage,
+    _pricing_fields,
+    _validate_fee_percent_override,
+    _validate_metadata,
+    _validate_recipients,
+)
+
+
+@pytest.fixture
+def mock_service(monkeypatch):
+    """Route every AsyncClient the settlement client opens to a handler."""
+
+    def install(handler):
+        real_async_client = httpx.AsyncClient
+
+        def async_client(*args, **kwargs):
+            return real_async_client(
+                *args, transport=httpx.MockTransport(handler), **kwargs
+            )
+
+        monkeypatch.setattr(httpx, "AsyncClient", async_client)
+
+    return install
+
+
+def _error_for(mock_service, response: httpx.Response):
+    mock_service(lambda request: response)
+    client = SettlementServiceClient(base_url="http://settlement.test")
+    with pytest.raises(SettlementServiceError) as exc_info:
+        asyncio.run(client.health_check())
+    return exc_info.value
+
+
+def test_structured_error_body(mock_service):
+    error = _error_for(
+        mock_service,
+        httpx.Response(
+            400,
+            json={
+                "error": {
+                    "code": "insufficient_funds",
+                    "message": "Payer balance too low",
+                    "details": {"shortfall_lamports": 1500},
+                }
+            },
+        ),
+    )
+
+    assert error.to_dict() == {
+        "error": "Invalid request",
+        "message": "Settlement service health_check failed: Payer balance too low",
+        "detail": "Payer balance too low",
+        "code": "insufficient_funds",
+        "details": {"shortfall_lamports": 1500},
+        "status_code": 400,
+    }
+
+
+def test_fastapi_detail_error_body(mock_service):
+    error = _error_for(
+        mock_service,
+        httpx.Response(404, json={"detail": "Not found"}),
+    )
+
+    assert error.to_dict() == {
+        "error": "Not found",
+        "message": "Settlement service health_check failed: Not found",
+        "detail": "Not found",
+        "status_code": 404,
+    }
+
+
+def test_flat_error_body(mock_service):
+    error = _error_for(
+        mock_service,
+        httpx.Response(
+            409, json={"error": "Duplicate settlement", "type": "conflict"}
+        ),
+    )
+
+    assert error.to_dict() == {
+        "error": "Conflict",
+        "message": "Settlement service health_check failed: Duplicate settlement",
+        "detail": "Duplicate settlement",
+        "status_code": 409,
+    }
+
+
+def test_plain_text_error_body(mock_service):
+    error = _error_for(
+        mock_service,
+        httpx.Response(502, text="Bad gateway"),
+    )
+
+    assert error.to_dict() == {
+        "error": "Server error",
+        "message": "Settlement service health_check failed: Bad gateway",
+        "detail": "Bad gateway",
+        "status_code": 502,
+    }
+
+
+def test_rate_limited_error_carries_retry_after(mock_service):
+    error = _error_for(
+        mock_service,
+        httpx.Response(
+            429,
+            json={"detail": "Too many requests"},
+            headers={"Retry-After": "3"},
+        ),
+    )
+
+    assert error.error_type == "Rate limited"
+    assert error.retry_after == 3.0
+    assert error.to_dict()["retry_after"] == 3.0
+
+
+@pytest.mark.parametrize(
+    "rate",
+    [-1.0, float("nan"), float("inf"), float("-inf"), 1e12],
+)
+def test_pricing_fields_rejects_out_of_range_rates(rate):
+    with pytest.raises(ValueError):
+        _pricing_fields(rate, 30.0, None)
+    with pytest.raises(ValueError):
+        _pricing_fields(None, None, rate)
+
+
+@pytest.mark.parametrize("rate", ["10", True, [10.0]])
+def test_pricing_fields_rejects_non_numeric_rates(rate):
+    with pytest.raises(ValueError, match="must be a number"):
+        _pricing_fields(10.0, rate, None)
+
+
+def test_pricing_fields_accepts_rates_up_to_the_cap():
+    assert _pricing_fields(0, ATP_MAX_COST_PER_MILLION_USD, None) == {
+        "input_cost_per_million_usd": 0,
+        "output_cost_per_million_usd": ATP_MAX_COST_PER_MILLION_USD,
+    }
+    assert _pricing_fields(None, None, 12.5) == {
+        "blended_cost_per_million_usd": 12.5,
+    }
+
+
+def test_pricing_fields_honors_a_custom_cap():
+    with pytest.raises(ValueError, match="exceeds the maximum of 5.0"):
+        _pricing_fields(10.0, 1.0, None, max_cost_per_million_usd=5.0)
+
+
+def test_client_cap_of_zero_rejects_paid_rates_before_sending(mock_service):
+    calls = []
+
+    def handler(request):
+        calls.append(request)
+        return httpx.Response(200, json={"status": "calculated"})
+
+    mock_service(handler)
+    client = SettlementServiceClient(
+        base_url="http://settlement.test", max_cost_per_million_usd=0
+    )
+    with pytest.raises(ValueError):
+        asyncio.run(
+            client.calculate_payment(
+                usage={"input_tokens": 100, "output_tokens": 50},
+                input_cost_per_million_usd=10.0,
+                output_cost_per_million_usd=30.0,
+            )
+        )
+    assert calls == []
+
+
+def test_env_cap_of_zero_is_not_replaced_by_the_default(monkeypatch):
+    monkeypatch.setenv("ATP_MAX_COST_PER_MILLION_USD", "0")
+    try:
+        assert importlib.reload(config).ATP_MAX_COST_PER_MILLION_USD == 0.0
+    finally:
+        monkeypatch.undo()
+        importlib.reload(config)
+
+
+@pytest.mark.parametrize(
+    "usage",
+    [
+        '{"input_tokens": 100, "output_tokens": 50}',
+        '"{\\"input_tokens\\": 100, \\"output_tokens\\": 50}"',
+        {"input_tokens": 100, "output_tokens": 50},
+    ],
+)
+def test_coerce_usage_decodes_encoded_objects(usage):
+    assert _coerce_usage(usage) == {"input_tokens": 100, "output_tokens": 50}
+
+
+@pytest.mark.parametrize(
+    "usage", ["not json", '{"input_tokens": ', "123", "null", "true", '"x"']
+)
+def test_coerce_usage_leaves_non_object_strings_unchanged(usage):
+    assert _coerce_usage(usage) == usage
+
+
+@pytest.mark.parametrize(
+    "payment_token, expected",
+    [(None, None), ("USDC", "USDC")],
+)
+def test_calculate_payment_omits_unset_payment_token(
+    mock_service, payment_token, expected
+):
+    sent = {}
+
+    def handler(request):
+        sent.update(json.loads(request.content))
+        return httpx.Response(200, json={"status": "calculated"})
+
+    mock_service(handler)
+    client = SettlementServiceClient(base_url="http://settlement.test")
+    asyncio.run(
+        client.calculate_payment(
+            usage={"input_tokens": 100, "output_tokens": 50},
+            input_cost_per_million_usd=10.0,
+            output_cost_per_million_usd=30.0,
+            payment_token=payment_token,
+        )
+    )
+
+    assert sent.get("payment_token") == expected
+    assert ("payment_token" in sent) == (expected is not None)
+
+
+@pytest.mark.parametrize(
+    "metadata",
+    [
+        ["invoice", "inv_1"],
+        {"invoice": 1},
+        {1: "inv_1"},
+        {f"key{i}": "v" for i in range(MAX_METADATA_ENTRIES + 1)},
+        {"note": "x" * MAX_METADATA_BYTES},
+    ],
+)
+def test_validate_metadata_rejects_invalid_maps(metadata):
+    with pytest.raises(ValueError):
+        _validate_metadata(metadata)
+
+
+def test_validate_metadata_accepts_bounded_string_map():
+    _validate_metadata(None)
+    _validate_metadata(
+        {f"key{i}": "v" for i in range(MAX_METADATA_ENTRIES)}
+    )
+
+
+def _settle_kwargs(**overrides):
+    kwargs = {
+        "private_key": "[1,2,3]",
+        "usage": {"input_tokens": 100, "output_tokens": 50},
+        "input_cost_per_million_usd": 10.0,
+        "output_cost_per_million_usd": 30.0,
+        "recipient_pubkey": "RecipientPublicKeyHere",
+    }
+    kwargs.update(overrides)
+    return kwargs
+
+
+def _assert_settle_rejected(mock_service, match, **overrides):
+    calls = []
+
+    def handler(request):
+        calls.append(request)
+        return httpx.Response(200, json={"status": "paid"})
+
+    mock_service(handler)
+    client = SettlementServiceClient(base_url="http://settlement.test")
+
+    with pytest.raises(ValueError, match=match):
+        asyncio.run(client.settle(**_settle_kwargs(**overrides)))
+
+    assert calls == []
+
+
+@pytest.mark.parametrize(
+    "referral, match",
+    [
+        ({"referral_pubkey": "ReferralPublicKeyHere"}, "together"),
+        ({"referral_share_bps": 500}, "together"),
+        (
+            {"referral_pubkey": "ReferralPublicKeyHere", "referral_share_bps": -1},
+            "between 0 and 10000",
+        ),
+        (
+            {"referral_pubkey": "ReferralPublicKeyHere", "referral_share_bps": 10_001},
+            "between 0 and 10000",
+        ),
+        (
+            {"referral_pubkey": "ReferralPublicKeyHere", "referral_share_bps": 12.5},
+            "integer",
+        ),
+        (
+            {"referral_pubkey": "ReferralPublicKeyHere", "referral_share_bps": True},
+            "integer",
+        ),
+        (
+            {"referral_pubkey": "ReferralPublicKeyHere", "referral_share_bps": "500"},
+            "integer",
+        ),
+    ],
+)
+def test_settle_rejects_invalid_referral(mock_service, referral, match):
+    _assert_settle_rejected(mock_service, match, **referral)
+
+
+def test_settle_with_blended_rate_and_recipients_needs_no_split_fields(
+    mock_service,
+):
+    sent = {}
+
+    def handler(request):
+        sent.update(json.loads(request.content))
+        return httpx.Response(200, json={"status": "paid"})
+
+    mock_service(handler)
+    client = SettlementServiceClient(base_url="http://settlement.test")
+    asyncio.run(
+        client.settle(
+            private_key=None,
+            usage={"input_tokens": 100, "output_tokens": 50},
+            blended_cost_per_million_usd=12.5,
+            recipients=[{"pubkey": "RecipientPublicKeyHere", "weight": 1}],
+            signer_id="hot-wallet-1",
+        )
+    )
+
+    assert sent["blended_cost_per_million_usd"] == 12.5
+    assert "input_cost_per_million_usd" not in sent
+    assert "recipient_pubkey" not in sent
+
+
+def _settle_with_caps(mock_service, quote, **caps):
+    calls = []
+
+    def handler(request):
+        calls.append(request.url.path)
+        if request.url.path.endswith("/calculate-payment"):
+            return httpx.Response(200, json=quote)
+        return httpx.Response(200, json={"status": "paid"})
+
+    mock_service(handler)
+    client = SettlementServiceClient(base_url="http://settlement.test")
+    asyncio.run(
+        client.settle(
+            private_key="[1,2,3]",
+            usage={"input_tokens": 1000, "output_tokens": 500},
+            input_cost_per_million_usd=10.0,
+            output_cost_per_million_usd=30.0,
+            recipient_pubkey="RecipientPublicKeyHere",
+            **caps,
+        )
+    )
+    return calls
+
+
+QUOTE = {
+    "status": "calculated",
+    "pricing": {"usd_cost": 0.025},
+    "payment_amounts": {"total_amount_token": 0.00125},
+}
+
+
+@pytest.mark.parametrize(
+    "caps",
+    [{"max_total_usd": 0.01}, {"max_total_token": 0.001}],
+)
+def test_settle_refuses_quote_above_spend_cap(mock_service, caps):
+    with pytest.raises(SettlementServiceError) as exc_info:
+        _settle_with_caps(mock_service, QUOTE, **caps)
+
+    assert exc_info.value.error_code == "max_total_exceeded"
+    (field, cap), = caps.items()
+    assert exc_info.value.error_details["field"] == field
+    assert exc_info.value.error_details["cap"] == cap
+
+
+def test_settle_refuses_when_quote_omits_capped_total(mock_service):
+    with pytest.raises(SettlementServiceError) as exc_info:
+        _settle_with_caps(
+            mock_service, {"status": "calculated"}, max_total_usd=1.0
+        )
+
+    assert exc_info.value.error_code == "max_total_unverified"
+
+
+@pytest.mark.parametrize(
+    "quote", [QUOTE, {"status": "skipped", "reason": "zero_cost"}]
+)
+def test_settle_proceeds_within_spend_cap(mock_service, quote):
+    calls = _settle_with_caps(
+        mock_service, quote, max_total_usd=0.05, max_total_token=0.002
+    )
+
+    assert calls == [
+        "/v1/settlement/calculate-payment",
+        "/v1/settlement/settle",
+    ]
+
+
+def test_settle_without_caps_skips_the_quote(mock_service):
+    assert _settle_with_caps(mock_service, QUOTE) == ["/v1/settlement/settle"]
+
+
+@pytest.mark.parametrize(
+    "recipient_pubkey, recipients, match",
+    [
+        ("RecipientPublicKeyHere", [{"pubkey": "A", "weight": 1}], "exactly one"),
+        (None, None, "exactly one"),
+        (None, [], "must not be empty"),
+        (None, [{"weight": 1}], "needs a pubkey"),
+        (None, [{"pubkey": "A", "weight": 0}], "positive number"),
+        (None, [{"pubkey": "A", "weight": -2}], "positive number"),
+        (None, [{"pubkey": "A", "weight": float("nan")}], "positive number"),
+        (None, [{"pubkey": "A", "weight": True}], "positive number"),
+        (None, [{"pubkey": "A", "weight": "1"}], "positive number"),
+    ],
+)
+def test_validate_recipients_rejects_invalid_input(
+    recipient_pubkey, recipients, match
+):
+    with pytest.raises(ValueError, match=match):
+        _validate_recipients(recipient_pubkey, recipients)
+
+
+@pytest.mark.parametrize(
+    "recipient_pubkey, recipients",
+    [
+        ("RecipientPublicKeyHere", None),
+        (None, [{"pubkey": "A", "weight": 3}, {"pubkey": "B", "weight": 0.5}]),
+    ],
+)
+def test_validate_recipients_accepts_one_recipient_field(
+    recipient_pubkey, recipients
+):
+    _validate_recipients(recipient_pubkey, recipients)
+
+
+@pytest.mark.parametrize(
+    "override", [-0.01, 0.51, float("nan"), float("inf"), True, "0.1"]
+)
+def test_validate_fee_percent_override_rejects_invalid_values(override):
+    with pytest.raises(ValueError, match="between 0.0 and 0.5"):
+        _validate_fee_percent_override(override)
+
+
+@pytest.mark.parametrize("override", [None, 0, 0.0, 0.03, 0.5])
+def test_validate_fee_percent_override_accepts_valid_values(override):
+    _validate_fee_percent_override(override)
+
+
+@pytest.mark.parametrize(
+    "overrides, match",
+    [
+        ({"signer_id": "hot-wallet-1"}, "not both"),
+        ({"private_key": None}, "required unless estimate_only"),
+        ({"estimate_only": True}, "must not be sent with estimate_only"),
+    ],
+)
+def test_settle_rejects_invalid_key_combinations(mock_service, overrides, match):
+    _assert_settle_rejected(mock_service, match, **overrides)
+
+
+def test_settle_estimate_only_sends_no_key(mock_service):
+    sent = {}
+
+    def handler(request):
+        sent.update(json.loads(request.content))
+        return httpx.Response(200, json={"status": "estimated"})
+
+    mock_service(handler)
+    client = SettlementServiceClient(base_url="http://settlement.test")
+    asyncio.run(
+        client.settle(**_settle_kwargs(private_key=None, estimate_only=True))
+    )
+
+    assert sent["estimate_only"] is True
+    assert "private_key" not in sent